@@ -0,0 +1,395 @@
+use std::io::{BufRead, Write};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+use serde_json::{json, Value};
+
+use basm_vm::io::{Captured, VmIo};
+use basm_vm::{Flag, Register, REGISTER_COUNT};
+
+use crate::{Debugger, Stop};
+
+#[cfg(test)]
+mod test;
+
+/// the only thread a program has
+const THREAD_ID: u64 = 1;
+
+const REGISTERS_REF: u64 = 1;
+const FLAGS_REF: u64 = 2;
+const VARIABLES_REF: u64 = 3;
+
+/// how many instructions run between checks for a request, such as a pause
+const SLICE: u64 = 10_000;
+
+/// a debug adapter protocol server, driving a [`Debugger`]
+pub struct DapServer<W> {
+    /// requests, read on a thread of their own so a running program can be paused
+    requests: Receiver<std::io::Result<Option<Value>>>,
+    output: W,
+    seq: u64,
+    session: Option<Session>,
+}
+
+struct Session {
+    dbg: Debugger,
+    path: String,
+    stdout: Captured,
+    stderr: Captured,
+    stop_on_entry: bool,
+    /// set once the program has exited, faulted or run off its end
+    halted: bool,
+}
+
+impl<W: Write> DapServer<W> {
+    pub fn new(mut input: impl BufRead + Send + 'static, output: W) -> Self {
+        let (sender, requests) = channel();
+        std::thread::spawn(move || loop {
+            let request = read(&mut input);
+            let last = !matches!(request, Ok(Some(_)));
+            if sender.send(request).is_err() || last {
+                break;
+            }
+        });
+        Self {
+            requests,
+            output,
+            seq: 1,
+            session: None,
+        }
+    }
+
+    /// handles requests until the client disconnects or the input ends
+    ///
+    /// a running program runs a slice at a time while no request is waiting
+    pub fn run(&mut self) -> std::io::Result<()> {
+        loop {
+            let running = self.session.as_ref().is_some_and(|s| s.dbg.interrupted());
+            let request = match running {
+                true => match self.requests.try_recv() {
+                    Ok(request) => request,
+                    Err(TryRecvError::Empty) => {
+                        self.resume(Debugger::carry_on)?;
+                        continue;
+                    }
+                    Err(TryRecvError::Disconnected) => Ok(None),
+                },
+                false => self.requests.recv().unwrap_or(Ok(None)),
+            };
+            let Some(request) = request? else {
+                return Ok(());
+            };
+            if !self.handle(&request)? {
+                return Ok(());
+            }
+        }
+    }
+
+    fn send(&mut self, mut message: Value) -> std::io::Result<()> {
+        message["seq"] = json!(self.seq);
+        self.seq += 1;
+        let body = message.to_string();
+        write!(self.output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+        self.output.flush()
+    }
+
+    fn respond(&mut self, request: &Value, body: Value) -> std::io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": true,
+            "body": body,
+        }))
+    }
+
+    fn fail(&mut self, request: &Value, message: &str) -> std::io::Result<()> {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": false,
+            "message": message,
+        }))
+    }
+
+    fn event(&mut self, event: &str, body: Value) -> std::io::Result<()> {
+        self.send(json!({ "type": "event", "event": event, "body": body }))
+    }
+
+    /// returns whether to keep serving
+    fn handle(&mut self, request: &Value) -> std::io::Result<bool> {
+        let args = &request["arguments"];
+        match request["command"].as_str().unwrap_or_default() {
+            "initialize" => {
+                let capabilities = json!({
+                    "supportsConfigurationDoneRequest": true,
+                    "supportsSingleThreadExecutionRequests": true,
+                });
+                self.respond(request, capabilities)?;
+                self.event("initialized", json!({}))?;
+            }
+            "launch" => match self.launch(args) {
+                Ok(()) => self.respond(request, json!({}))?,
+                Err(message) => self.fail(request, &message)?,
+            },
+            "setBreakpoints" => {
+                let body = self.set_breakpoints(args);
+                self.respond(request, body)?;
+            }
+            "configurationDone" => {
+                self.respond(request, json!({}))?;
+                match self.session.as_ref().map(|s| s.stop_on_entry) {
+                    Some(true) => self.stopped("entry", None)?,
+                    Some(false) => self.resume(Debugger::cont)?,
+                    None => (),
+                }
+            }
+            "threads" => {
+                let threads = json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] });
+                self.respond(request, threads)?;
+            }
+            "stackTrace" => {
+                let body = self.stack_trace();
+                self.respond(request, body)?;
+            }
+            "scopes" => {
+                let scopes = json!({ "scopes": [
+                    { "name": "Registers", "variablesReference": REGISTERS_REF, "expensive": false },
+                    { "name": "Flags", "variablesReference": FLAGS_REF, "expensive": false },
+                    { "name": "Variables", "variablesReference": VARIABLES_REF, "expensive": false },
+                ]});
+                self.respond(request, scopes)?;
+            }
+            "variables" => {
+                let reference = args["variablesReference"].as_u64().unwrap_or_default();
+                let variables = self.variables(reference);
+                self.respond(request, json!({ "variables": variables }))?;
+            }
+            "continue" => {
+                self.respond(request, json!({ "allThreadsContinued": true }))?;
+                self.resume(Debugger::cont)?;
+            }
+            "next" => {
+                self.respond(request, json!({}))?;
+                self.resume(Debugger::step_over)?;
+            }
+            "stepIn" => {
+                self.respond(request, json!({}))?;
+                self.resume(|dbg| dbg.step(1))?;
+            }
+            "stepOut" => {
+                self.respond(request, json!({}))?;
+                self.resume(Debugger::finish)?;
+            }
+            "pause" => {
+                self.respond(request, json!({}))?;
+                if let Some(session) = self.session.as_mut().filter(|s| s.dbg.interrupted()) {
+                    session.dbg.pause();
+                    self.stopped("pause", None)?;
+                }
+            }
+            "disconnect" => {
+                self.respond(request, json!({}))?;
+                return Ok(false);
+            }
+            command => self.fail(request, &format!("unsupported request `{command}`"))?,
+        }
+        Ok(true)
+    }
+
+    fn launch(&mut self, args: &Value) -> Result<(), String> {
+        let Some(path) = args["program"].as_str() else {
+            return Err("launch needs a `program`".to_string());
+        };
+        let src = std::fs::read_to_string(path).map_err(|e| format!("{path}: {e}"))?;
+        let mut dbg = Debugger::new(&src).map_err(|errs| format!("{path} has errors:{errs}"))?;
+        dbg.slice = Some(SLICE);
+        let (stdout, stderr) = (Captured::default(), Captured::default());
+        // stdin carries the protocol, so the program gets none
+        dbg.vm.io = VmIo::new(std::io::empty(), stdout.clone(), stderr.clone());
+        self.session = Some(Session {
+            dbg,
+            path: path.to_string(),
+            stdout,
+            stderr,
+            stop_on_entry: args["stopOnEntry"].as_bool().unwrap_or(false),
+            halted: false,
+        });
+        Ok(())
+    }
+
+    fn set_breakpoints(&mut self, args: &Value) -> Value {
+        let Some(session) = &mut self.session else {
+            return json!({ "breakpoints": [] });
+        };
+        let dbg = &mut session.dbg;
+        dbg.clear_breakpoints();
+        let lines = args["breakpoints"].as_array().cloned().unwrap_or_default();
+        let breakpoints: Vec<_> = lines
+            .iter()
+            .map(|bp| {
+                let line = bp["line"].as_u64().unwrap_or_default() as u32;
+                let address = dbg.vm.symbols.line_address(line.saturating_sub(1));
+                match address {
+                    Some(address) => {
+                        let bp = dbg.set_breakpoint(address);
+                        let line = dbg.vm.symbols.line(address).map_or(line, |l| l + 1);
+                        json!({ "id": bp.id, "verified": true, "line": line })
+                    }
+                    None => json!({ "verified": false, "line": line }),
+                }
+            })
+            .collect();
+        json!({ "breakpoints": breakpoints })
+    }
+
+    fn stack_trace(&self) -> Value {
+        let Some(session) = &self.session else {
+            return json!({ "stackFrames": [], "totalFrames": 0 });
+        };
+        let symbols = &session.dbg.vm.symbols;
+        let frames: Vec<_> = session
+            .dbg
+            .frames()
+            .into_iter()
+            .enumerate()
+            .map(|(id, address)| {
+                let name = match symbols.enclosing_label(address) {
+                    Some((label, 0)) => label.to_string(),
+                    Some((label, offset)) => format!("{label}+{offset}"),
+                    None => format!("{address:#06x}"),
+                };
+                let line = symbols.line(address).map_or(0, |line| line + 1);
+                json!({
+                    "id": id,
+                    "name": name,
+                    "line": line,
+                    "column": 1,
+                    "source": { "path": session.path },
+                    "instructionPointerReference": format!("{address:#06x}"),
+                })
+            })
+            .collect();
+        json!({ "totalFrames": frames.len(), "stackFrames": frames })
+    }
+
+    fn variables(&self, reference: u64) -> Vec<Value> {
+        let Some(session) = &self.session else {
+            return Vec::new();
+        };
+        let vm = &session.dbg.vm;
+        let variable = |name: String, value: String| json!({ "name": name, "value": value, "variablesReference": 0 });
+        match reference {
+            REGISTERS_REF => (0..REGISTER_COUNT as u16)
+                .filter_map(|r| Register::try_from(r).ok())
+                .map(|r| variable(r.to_string(), format!("{:#06x}", vm.reg(r))))
+                .chain([variable("rip".to_string(), format!("{:#06x}", vm.rip))])
+                .collect(),
+            FLAGS_REF => Flag::ALL
+                .into_iter()
+                .map(|f| variable(format!("{f:?}"), vm.flag(f).to_string()))
+                .collect(),
+            VARIABLES_REF => vm
+                .symbols
+                .variables
+                .iter()
+                .map(|var| {
+                    let value = vm
+                        .slice(var.address, var.len)
+                        .map(|bytes| format!("{:?}", String::from_utf8_lossy(bytes)))
+                        .unwrap_or_default();
+                    variable(var.name.clone(), value)
+                })
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// runs the program, then reports its output & why it stopped
+    fn resume(&mut self, run: impl FnOnce(&mut Debugger) -> Stop) -> std::io::Result<()> {
+        let Some(session) = &mut self.session else {
+            return Ok(());
+        };
+        if session.halted {
+            return self.terminate(None);
+        }
+        let stop = run(&mut session.dbg);
+        let out = session.stdout.take();
+        let err = session.stderr.take();
+        for (category, bytes) in [("stdout", out), ("stderr", err)] {
+            if !bytes.is_empty() {
+                let output = String::from_utf8_lossy(&bytes);
+                self.event("output", json!({ "category": category, "output": output }))?;
+            }
+        }
+        match stop {
+            Stop::Interrupted => Ok(()),
+            Stop::Step => self.stopped("step", None),
+            Stop::Breakpoint(id) => self.stopped("breakpoint", Some(id)),
+            Stop::Exited(code) => self.terminate(Some(code)),
+            Stop::End => self.terminate(Some(0)),
+            Stop::Faulted(err) => {
+                let mut output = format!("{err}\n");
+                if let Some(session) = &mut self.session {
+                    session.halted = true;
+                    output.push_str(&session.dbg.backtrace());
+                }
+                self.event("output", json!({ "category": "stderr", "output": output }))?;
+                let body = json!({
+                    "reason": "exception",
+                    "description": err.kind.to_string(),
+                    "threadId": THREAD_ID,
+                    "allThreadsStopped": true,
+                });
+                self.event("stopped", body)
+            }
+        }
+    }
+
+    fn stopped(&mut self, reason: &str, breakpoint: Option<usize>) -> std::io::Result<()> {
+        let mut body = json!({
+            "reason": reason,
+            "threadId": THREAD_ID,
+            "allThreadsStopped": true,
+        });
+        if let Some(id) = breakpoint {
+            body["hitBreakpointIds"] = json!([id]);
+        }
+        self.event("stopped", body)
+    }
+
+    /// ends the session, exiting with 1 if the program never exited itself
+    fn terminate(&mut self, code: Option<u8>) -> std::io::Result<()> {
+        if let Some(session) = &mut self.session {
+            session.halted = true;
+        }
+        self.event("exited", json!({ "exitCode": code.unwrap_or(1) }))?;
+        self.event("terminated", json!({}))
+    }
+}
+
+/// reads a message framed by a `Content-Length` header
+fn read(input: &mut impl BufRead) -> std::io::Result<Option<Value>> {
+    let mut len = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(n) = line.strip_prefix("Content-Length:") {
+            len = n.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(len) = len else {
+        return Err(std::io::Error::other("message without a Content-Length"));
+    };
+    let mut body = vec![0; len];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(std::io::Error::other)
+}
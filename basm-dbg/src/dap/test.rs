@@ -0,0 +1,238 @@
+use expect_test::{expect, Expect};
+use serde_json::Value;
+
+use super::DapServer;
+
+/// plays a recorded session of requests, one per line, against a program
+///
+/// `$PROGRAM` in a request is replaced by the path of the program
+fn check(name: &str, src: &str, session: &str, expect: Expect) {
+    let dir = std::env::temp_dir().join(format!("basm-dap-{}-{name}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("program.basm");
+    std::fs::write(&path, src).unwrap();
+    let mut input = Vec::new();
+    for line in session.lines().map(str::trim).filter(|l| !l.is_empty()) {
+        let body = line.replace("$PROGRAM", &path.to_string_lossy());
+        input.extend(format!("Content-Length: {}\r\n\r\n{body}", body.len()).bytes());
+    }
+    let mut output = Vec::new();
+    DapServer::new(std::io::Cursor::new(input), &mut output)
+        .run()
+        .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    let output = String::from_utf8(output).unwrap();
+    let mut messages = Vec::new();
+    let mut rest = output.as_str();
+    while let Some((header, body)) = rest.split_once("\r\n\r\n") {
+        let len: usize = header["Content-Length: ".len()..].parse().unwrap();
+        let message: Value = serde_json::from_str(&body[..len]).unwrap();
+        // paths differ between machines
+        let message = message.to_string();
+        messages.push(message.replace(&*path.to_string_lossy(), "$PROGRAM"));
+        rest = &body[len..];
+    }
+    expect.assert_eq(&messages.join("\n"));
+}
+
+const PROGRAM: &str = "
+    msg str \"hi\", 10
+
+_start:
+    mov rax, 1
+    call print
+    mov rax, 60
+    mov rdi, 0
+    syscall
+
+print:
+    mov rax, 1
+    mov rdi, 1
+    mov rsi, msg
+    mov rdx, 3
+    syscall
+    ret
+";
+
+#[test]
+fn breakpoint_session() {
+    check(
+        "breakpoint",
+        PROGRAM,
+        r#"
+        {"seq":1,"type":"request","command":"initialize","arguments":{"adapterID":"basm"}}
+        {"seq":2,"type":"request","command":"launch","arguments":{"program":"$PROGRAM"}}
+        {"seq":3,"type":"request","command":"setBreakpoints","arguments":{"source":{"path":"$PROGRAM"},"breakpoints":[{"line":13},{"line":100}]}}
+        {"seq":4,"type":"request","command":"configurationDone"}
+        {"seq":5,"type":"request","command":"threads"}
+        {"seq":6,"type":"request","command":"stackTrace","arguments":{"threadId":1}}
+        {"seq":7,"type":"request","command":"scopes","arguments":{"frameId":0}}
+        {"seq":8,"type":"request","command":"variables","arguments":{"variablesReference":3}}
+        {"seq":9,"type":"request","command":"continue","arguments":{"threadId":1}}
+        {"seq":10,"type":"request","command":"disconnect"}
+        "#,
+        expect![[r#"
+            {"body":{"supportsConfigurationDoneRequest":true,"supportsSingleThreadExecutionRequests":true},"command":"initialize","request_seq":1,"seq":1,"success":true,"type":"response"}
+            {"body":{},"event":"initialized","seq":2,"type":"event"}
+            {"body":{},"command":"launch","request_seq":2,"seq":3,"success":true,"type":"response"}
+            {"body":{"breakpoints":[{"id":1,"line":13,"verified":true},{"line":100,"verified":false}]},"command":"setBreakpoints","request_seq":3,"seq":4,"success":true,"type":"response"}
+            {"body":{},"command":"configurationDone","request_seq":4,"seq":5,"success":true,"type":"response"}
+            {"body":{"allThreadsStopped":true,"hitBreakpointIds":[1],"reason":"breakpoint","threadId":1},"event":"stopped","seq":6,"type":"event"}
+            {"body":{"threads":[{"id":1,"name":"main"}]},"command":"threads","request_seq":5,"seq":7,"success":true,"type":"response"}
+            {"body":{"stackFrames":[{"column":1,"id":0,"instructionPointerReference":"0x003b","line":13,"name":"print+8","source":{"path":"$PROGRAM"}},{"column":1,"id":1,"instructionPointerReference":"0x0013","line":6,"name":"_start+8","source":{"path":"$PROGRAM"}}],"totalFrames":2},"command":"stackTrace","request_seq":6,"seq":8,"success":true,"type":"response"}
+            {"body":{"scopes":[{"expensive":false,"name":"Registers","variablesReference":1},{"expensive":false,"name":"Flags","variablesReference":2},{"expensive":false,"name":"Variables","variablesReference":3}]},"command":"scopes","request_seq":7,"seq":9,"success":true,"type":"response"}
+            {"body":{"variables":[{"name":"msg","value":"\"hi\\n\"","variablesReference":0}]},"command":"variables","request_seq":8,"seq":10,"success":true,"type":"response"}
+            {"body":{"allThreadsContinued":true},"command":"continue","request_seq":9,"seq":11,"success":true,"type":"response"}
+            {"body":{"category":"stdout","output":"hi\n"},"event":"output","seq":12,"type":"event"}
+            {"body":{"exitCode":0},"event":"exited","seq":13,"type":"event"}
+            {"body":{},"event":"terminated","seq":14,"type":"event"}
+            {"body":{},"command":"disconnect","request_seq":10,"seq":15,"success":true,"type":"response"}"#]],
+    );
+}
+
+#[test]
+fn stepping_session() {
+    check(
+        "stepping",
+        PROGRAM,
+        r#"
+        {"seq":1,"type":"request","command":"initialize","arguments":{"adapterID":"basm"}}
+        {"seq":2,"type":"request","command":"launch","arguments":{"program":"$PROGRAM","stopOnEntry":true}}
+        {"seq":3,"type":"request","command":"configurationDone"}
+        {"seq":4,"type":"request","command":"stepIn","arguments":{"threadId":1}}
+        {"seq":5,"type":"request","command":"stepIn","arguments":{"threadId":1}}
+        {"seq":6,"type":"request","command":"variables","arguments":{"variablesReference":1}}
+        {"seq":7,"type":"request","command":"stepOut","arguments":{"threadId":1}}
+        {"seq":8,"type":"request","command":"next","arguments":{"threadId":1}}
+        {"seq":9,"type":"request","command":"variables","arguments":{"variablesReference":2}}
+        {"seq":10,"type":"request","command":"evaluate","arguments":{"expression":"rax"}}
+        {"seq":11,"type":"request","command":"continue","arguments":{"threadId":1}}
+        "#,
+        expect![[r#"
+            {"body":{"supportsConfigurationDoneRequest":true,"supportsSingleThreadExecutionRequests":true},"command":"initialize","request_seq":1,"seq":1,"success":true,"type":"response"}
+            {"body":{},"event":"initialized","seq":2,"type":"event"}
+            {"body":{},"command":"launch","request_seq":2,"seq":3,"success":true,"type":"response"}
+            {"body":{},"command":"configurationDone","request_seq":3,"seq":4,"success":true,"type":"response"}
+            {"body":{"allThreadsStopped":true,"reason":"entry","threadId":1},"event":"stopped","seq":5,"type":"event"}
+            {"body":{},"command":"stepIn","request_seq":4,"seq":6,"success":true,"type":"response"}
+            {"body":{"allThreadsStopped":true,"reason":"step","threadId":1},"event":"stopped","seq":7,"type":"event"}
+            {"body":{},"command":"stepIn","request_seq":5,"seq":8,"success":true,"type":"response"}
+            {"body":{"allThreadsStopped":true,"reason":"step","threadId":1},"event":"stopped","seq":9,"type":"event"}
+            {"body":{"variables":[{"name":"rax","value":"0x0001","variablesReference":0},{"name":"rbx","value":"0x0000","variablesReference":0},{"name":"rcx","value":"0x0000","variablesReference":0},{"name":"rdx","value":"0x0000","variablesReference":0},{"name":"rsi","value":"0x0000","variablesReference":0},{"name":"rdi","value":"0x0000","variablesReference":0},{"name":"rsp","value":"0xfffd","variablesReference":0},{"name":"rbp","value":"0x0000","variablesReference":0},{"name":"r08","value":"0x0000","variablesReference":0},{"name":"r09","value":"0x0000","variablesReference":0},{"name":"r10","value":"0x0000","variablesReference":0},{"name":"r11","value":"0x0000","variablesReference":0},{"name":"r12","value":"0x0000","variablesReference":0},{"name":"r13","value":"0x0000","variablesReference":0},{"name":"r14","value":"0x0000","variablesReference":0},{"name":"r15","value":"0x0000","variablesReference":0},{"name":"rip","value":"0x0033","variablesReference":0}]},"command":"variables","request_seq":6,"seq":10,"success":true,"type":"response"}
+            {"body":{},"command":"stepOut","request_seq":7,"seq":11,"success":true,"type":"response"}
+            {"body":{"category":"stdout","output":"hi\n"},"event":"output","seq":12,"type":"event"}
+            {"body":{"allThreadsStopped":true,"reason":"step","threadId":1},"event":"stopped","seq":13,"type":"event"}
+            {"body":{},"command":"next","request_seq":8,"seq":14,"success":true,"type":"response"}
+            {"body":{"allThreadsStopped":true,"reason":"step","threadId":1},"event":"stopped","seq":15,"type":"event"}
+            {"body":{"variables":[{"name":"Sf","value":"false","variablesReference":0},{"name":"Zf","value":"false","variablesReference":0},{"name":"Cf","value":"false","variablesReference":0},{"name":"Af","value":"false","variablesReference":0},{"name":"Pf","value":"false","variablesReference":0},{"name":"Of","value":"false","variablesReference":0}]},"command":"variables","request_seq":9,"seq":16,"success":true,"type":"response"}
+            {"command":"evaluate","message":"unsupported request `evaluate`","request_seq":10,"seq":17,"success":false,"type":"response"}
+            {"body":{"allThreadsContinued":true},"command":"continue","request_seq":11,"seq":18,"success":true,"type":"response"}
+            {"body":{"exitCode":0},"event":"exited","seq":19,"type":"event"}
+            {"body":{},"event":"terminated","seq":20,"type":"event"}"#]],
+    );
+}
+
+#[test]
+fn fault_session() {
+    check(
+        "fault",
+        "
+_start:
+    pop rax",
+        r#"
+        {"seq":1,"type":"request","command":"launch","arguments":{"program":"$PROGRAM"}}
+        {"seq":2,"type":"request","command":"configurationDone"}
+        {"seq":3,"type":"request","command":"continue","arguments":{"threadId":1}}
+        "#,
+        expect![[r#"
+            {"body":{},"command":"launch","request_seq":1,"seq":1,"success":true,"type":"response"}
+            {"body":{},"command":"configurationDone","request_seq":2,"seq":2,"success":true,"type":"response"}
+            {"body":{"category":"stderr","output":"fault at 0x0006: pop from an empty stack\nrax: 0x0000 rbx: 0x0000 rcx: 0x0000 rdx: 0x0000\nrsi: 0x0000 rdi: 0x0000 rsp: 0xffff rbp: 0x0000\nr08: 0x0000 r09: 0x0000 r10: 0x0000 r11: 0x0000\nr12: 0x0000 r13: 0x0000 r14: 0x0000 r15: 0x0000\nrip: 0x0006 flags: []\n#0 0x0006 _start (line 3)\n"},"event":"output","seq":3,"type":"event"}
+            {"body":{"allThreadsStopped":true,"description":"pop from an empty stack","reason":"exception","threadId":1},"event":"stopped","seq":4,"type":"event"}
+            {"body":{"allThreadsContinued":true},"command":"continue","request_seq":3,"seq":5,"success":true,"type":"response"}
+            {"body":{"exitCode":1},"event":"exited","seq":6,"type":"event"}
+            {"body":{},"event":"terminated","seq":7,"type":"event"}"#]],
+    );
+}
+
+#[test]
+fn entry_breakpoint_session() {
+    check(
+        "entry",
+        PROGRAM,
+        r#"
+        {"seq":1,"type":"request","command":"launch","arguments":{"program":"$PROGRAM"}}
+        {"seq":2,"type":"request","command":"setBreakpoints","arguments":{"source":{"path":"$PROGRAM"},"breakpoints":[{"line":5}]}}
+        {"seq":3,"type":"request","command":"configurationDone"}
+        {"seq":4,"type":"request","command":"continue","arguments":{"threadId":1}}
+        "#,
+        expect![[r#"
+            {"body":{},"command":"launch","request_seq":1,"seq":1,"success":true,"type":"response"}
+            {"body":{"breakpoints":[{"id":1,"line":5,"verified":true}]},"command":"setBreakpoints","request_seq":2,"seq":2,"success":true,"type":"response"}
+            {"body":{},"command":"configurationDone","request_seq":3,"seq":3,"success":true,"type":"response"}
+            {"body":{"allThreadsStopped":true,"hitBreakpointIds":[1],"reason":"breakpoint","threadId":1},"event":"stopped","seq":4,"type":"event"}
+            {"body":{"allThreadsContinued":true},"command":"continue","request_seq":4,"seq":5,"success":true,"type":"response"}
+            {"body":{"category":"stdout","output":"hi\n"},"event":"output","seq":6,"type":"event"}
+            {"body":{"exitCode":0},"event":"exited","seq":7,"type":"event"}
+            {"body":{},"event":"terminated","seq":8,"type":"event"}"#]],
+    );
+}
+
+#[test]
+fn launch_errors() {
+    check(
+        "errors",
+        "
+_start:
+    mov rax,
+    jmp nowhere",
+        r#"
+        {"seq":1,"type":"request","command":"launch","arguments":{"program":"$PROGRAM"}}
+        "#,
+        expect![[
+            r#"{"command":"launch","message":"$PROGRAM has errors:\nInputError(InvalidArgCount { exp: 2, got: 1 })","request_seq":1,"seq":1,"success":false,"type":"response"}"#
+        ]],
+    );
+}
+
+#[test]
+fn pause_session() {
+    check(
+        "pause",
+        "
+_start:
+    jmp _start",
+        r#"
+        {"seq":1,"type":"request","command":"launch","arguments":{"program":"$PROGRAM"}}
+        {"seq":2,"type":"request","command":"configurationDone"}
+        {"seq":3,"type":"request","command":"pause","arguments":{"threadId":1}}
+        {"seq":4,"type":"request","command":"stackTrace","arguments":{"threadId":1}}
+        {"seq":5,"type":"request","command":"disconnect"}
+        "#,
+        expect![[r#"
+            {"body":{},"command":"launch","request_seq":1,"seq":1,"success":true,"type":"response"}
+            {"body":{},"command":"configurationDone","request_seq":2,"seq":2,"success":true,"type":"response"}
+            {"body":{},"command":"pause","request_seq":3,"seq":3,"success":true,"type":"response"}
+            {"body":{"allThreadsStopped":true,"reason":"pause","threadId":1},"event":"stopped","seq":4,"type":"event"}
+            {"body":{"stackFrames":[{"column":1,"id":0,"instructionPointerReference":"0x0006","line":3,"name":"_start","source":{"path":"$PROGRAM"}}],"totalFrames":1},"command":"stackTrace","request_seq":4,"seq":5,"success":true,"type":"response"}
+            {"body":{},"command":"disconnect","request_seq":5,"seq":6,"success":true,"type":"response"}"#]],
+    );
+}
+
+#[test]
+fn launch_undefined_label() {
+    check(
+        "undefined",
+        "
+_start:
+    jmp nowhere",
+        r#"
+        {"seq":1,"type":"request","command":"launch","arguments":{"program":"$PROGRAM"}}
+        {"seq":2,"type":"request","command":"disconnect"}
+        "#,
+        expect![[r#"
+            {"command":"launch","message":"$PROGRAM has errors:\nMissingSymbol(\"nowhere\")","request_seq":1,"seq":1,"success":false,"type":"response"}
+            {"body":{},"command":"disconnect","request_seq":2,"seq":2,"success":true,"type":"response"}"#]],
+    );
+}
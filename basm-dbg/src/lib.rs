@@ -0,0 +1,466 @@
+use std::io::Write;
+
+use basm_vm::decode::decode_at;
+use basm_vm::reparse::register;
+use basm_vm::{
+    BasmVM, Flag, Register, RuntimeError, Sequence, StepEvent, VmError, INS_SIZE, MEM_SIZE,
+};
+
+pub mod dap;
+
+#[cfg(test)]
+mod test;
+
+pub const HELP: &str = "\
+commands:
+  break <label|line>    stop before the instruction, `b`
+  delete <n>            remove breakpoint n, `d`
+  breakpoints           list the breakpoints
+  step [n]              run n instructions, entering calls, `s`
+  next                  run one instruction, stepping over calls, `n`
+  finish                run until the current function returns
+  back [n]              undo n instructions
+  rewind <count>        undo until count instructions have run
+  continue              run until a breakpoint or the end, `c`
+  run                   restart the program and continue, `r`
+  regs                  print the registers
+  flags                 print the flags
+  print <name>          print a register or variable, `p`
+  x <where> [len]       dump memory at an address, register or variable
+  where                 print where the program is stopped
+  backtrace             print the calls that led here, `bt`
+  save <file>           write a snapshot of the vm to a file
+  quit                  leave the debugger, `q`";
+
+/// how many instructions can be stepped back
+pub const HISTORY: usize = 10_000;
+
+pub struct Debugger {
+    pub vm: BasmVM,
+    src: String,
+    breakpoints: Vec<Breakpoint>,
+    next_id: usize,
+    /// the rip & instruction count a breakpoint last stopped at, which
+    /// resuming from runs past the breakpoint
+    at_breakpoint: Option<(u16, u64)>,
+    /// the most instructions a command runs before it is interrupted, leaving
+    /// [`Debugger::carry_on`] to run the rest
+    pub slice: Option<u64>,
+    /// the stop condition of an interrupted command
+    pending: Option<Box<StopWhen>>,
+}
+
+/// whether to stop after an instruction has run
+type StopWhen = dyn FnMut(&BasmVM, &Sequence) -> bool;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub id: usize,
+    pub address: u16,
+}
+
+/// why the program stopped running
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// it finished the step asked for
+    Step,
+    /// it ran for a whole [`Debugger::slice`], without finishing
+    Interrupted,
+    /// it reached a breakpoint, holding its id
+    Breakpoint(usize),
+    Exited(u8),
+    /// it ran past its last instruction
+    End,
+    Faulted(RuntimeError),
+}
+
+/// whether to keep reading commands
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Quit,
+}
+
+impl Debugger {
+    /// loads a program, stopped before its first instruction
+    pub fn new(src: &str) -> Result<Self, VmError> {
+        let mut vm = BasmVM::parse(src)?;
+        vm.options.history = HISTORY;
+        vm.reset();
+        Ok(Self {
+            vm,
+            src: src.to_string(),
+            breakpoints: Vec::new(),
+            next_id: 1,
+            at_breakpoint: None,
+            slice: None,
+            pending: None,
+        })
+    }
+
+    pub fn src(&self) -> &str {
+        &self.src
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    pub fn set_breakpoint(&mut self, address: u16) -> Breakpoint {
+        let bp = Breakpoint {
+            id: self.next_id,
+            address,
+        };
+        self.next_id += 1;
+        self.breakpoints.push(bp);
+        bp
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+    }
+
+    /// the address of each frame, from rip out to the outermost call
+    pub fn frames(&self) -> Vec<u16> {
+        self.vm.backtrace()
+    }
+
+    /// runs each line of a script as a command, echoing it first
+    ///
+    /// empty lines & lines starting with `#` are skipped
+    pub fn run_script(&mut self, script: &str, out: &mut impl Write) -> std::io::Result<()> {
+        for line in script.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            writeln!(out, "(basm) {line}")?;
+            if self.execute(line, out)? == Flow::Quit {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn execute(&mut self, command: &str, out: &mut impl Write) -> std::io::Result<Flow> {
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            return Ok(Flow::Continue);
+        };
+        let args: Vec<_> = words.collect();
+        match (name, args.as_slice()) {
+            ("break" | "b", [at]) => self.add_breakpoint(at, out)?,
+            ("delete" | "d", [id]) => match id.parse().ok().and_then(|id| self.remove(id)) {
+                Some(bp) => writeln!(out, "deleted breakpoint {}", bp.id)?,
+                None => writeln!(out, "no breakpoint {id}")?,
+            },
+            ("breakpoints", []) => {
+                for bp in &self.breakpoints {
+                    let at = self.vm.symbols.describe(bp.address);
+                    writeln!(out, "{}: {:#06x} {at}", bp.id, bp.address)?;
+                }
+            }
+            ("step" | "s", []) => {
+                let stop = self.step(1);
+                self.report(stop, out)?
+            }
+            ("step" | "s", [n]) => match n.parse() {
+                Ok(n) => {
+                    let stop = self.step(n);
+                    self.report(stop, out)?
+                }
+                Err(_) => writeln!(out, "expected a count, got `{n}`")?,
+            },
+            ("next" | "n", []) => {
+                let stop = self.step_over();
+                self.report(stop, out)?
+            }
+            ("finish", []) => {
+                let stop = self.finish();
+                self.report(stop, out)?
+            }
+            ("back", []) => self.back(1, out)?,
+            ("back", [n]) => match n.parse() {
+                Ok(n) => self.back(n, out)?,
+                Err(_) => writeln!(out, "expected a count, got `{n}`")?,
+            },
+            ("rewind", [count]) => match count.parse() {
+                Ok(count) => match self.rewind_to(count) {
+                    true => self.location(out)?,
+                    false => writeln!(out, "history does not reach instruction {count}")?,
+                },
+                Err(_) => writeln!(out, "expected a count, got `{count}`")?,
+            },
+            ("continue" | "c", []) => {
+                let stop = self.cont();
+                self.report(stop, out)?
+            }
+            ("run" | "r", []) => {
+                self.restart()?;
+                let stop = self.cont();
+                self.report(stop, out)?
+            }
+            ("regs", []) => writeln!(out, "{}", self.vm.registers())?,
+            ("flags", []) => {
+                let flags: Vec<_> = Flag::ALL
+                    .into_iter()
+                    .filter(|&f| self.vm.flag(f))
+                    .map(|f| format!("{f:?}"))
+                    .collect();
+                writeln!(out, "flags: [{}]", flags.join(" "))?
+            }
+            ("print" | "p", [name]) => self.print(name, out)?,
+            ("x", [at]) => self.dump(at, None, out)?,
+            ("x", [at, len]) => match parse_number(len) {
+                Some(len) => self.dump(at, Some(len), out)?,
+                None => writeln!(out, "expected a length, got `{len}`")?,
+            },
+            ("where", []) => self.location(out)?,
+            ("backtrace" | "bt", []) => write!(out, "{}", self.backtrace())?,
+            ("save", [path]) => match self.vm.save_snapshot(path) {
+                Ok(()) => writeln!(out, "saved a snapshot to {path}")?,
+                Err(err) => writeln!(out, "failed to save {path}: {err}")?,
+            },
+            ("help" | "h", []) => writeln!(out, "{HELP}")?,
+            ("quit" | "q", []) => return Ok(Flow::Quit),
+            _ => writeln!(out, "unknown command `{command}`, try `help`")?,
+        }
+        Ok(Flow::Continue)
+    }
+
+    fn add_breakpoint(&mut self, at: &str, out: &mut impl Write) -> std::io::Result<()> {
+        let address = match at.parse::<u32>() {
+            Ok(line) => self.vm.symbols.line_address(line.saturating_sub(1)),
+            Err(_) => self.vm.symbols.label(at),
+        };
+        let Some(address) = address else {
+            return writeln!(out, "no instruction at `{at}`");
+        };
+        let bp = self.set_breakpoint(address);
+        let at = self.vm.symbols.describe(address);
+        writeln!(out, "breakpoint {} at {address:#06x} {at}", bp.id)
+    }
+
+    fn remove(&mut self, id: usize) -> Option<Breakpoint> {
+        let index = self.breakpoints.iter().position(|bp| bp.id == id)?;
+        Some(self.breakpoints.remove(index))
+    }
+
+    /// reloads the program, keeping its options & streams
+    pub fn restart(&mut self) -> std::io::Result<()> {
+        let Ok(mut vm) = BasmVM::parse(&self.src) else {
+            return Err(std::io::Error::other("failed to reload the program"));
+        };
+        vm.options = self.vm.options.clone();
+        vm.io = std::mem::take(&mut self.vm.io);
+        vm.syscalls = std::mem::take(&mut self.vm.syscalls);
+        vm.fs = std::mem::take(&mut self.vm.fs);
+        vm.tracer = self.vm.tracer.take();
+        vm.profiler = self.vm.profiler.take();
+        vm.coverage = self.vm.coverage.take();
+        vm.replay = self.vm.replay.take();
+        vm.reset();
+        self.vm = vm;
+        self.at_breakpoint = None;
+        self.pending = None;
+        Ok(())
+    }
+
+    /// runs `n` instructions, entering calls
+    pub fn step(&mut self, n: usize) -> Stop {
+        let mut left = n;
+        self.resume(move |_, _| {
+            left = left.saturating_sub(1);
+            left == 0
+        })
+    }
+
+    /// runs one instruction, running any call through to its return
+    pub fn step_over(&mut self) -> Stop {
+        let rip = self.vm.rip;
+        if !matches!(decode_at(&self.vm.mem, rip), Some(Sequence::Call(_))) {
+            return self.step(1);
+        }
+        let rsp = self.vm.reg(Register::RSP);
+        let ret = rip.wrapping_add(INS_SIZE);
+        self.resume(move |vm, _| vm.rip == ret && vm.reg(Register::RSP) >= rsp)
+    }
+
+    /// runs until the current function returns
+    pub fn finish(&mut self) -> Stop {
+        let rsp = self.vm.reg(Register::RSP);
+        self.resume(move |vm, seq| matches!(seq, Sequence::Ret) && vm.reg(Register::RSP) > rsp)
+    }
+
+    /// undoes up to `n` instructions, returning how many were undone
+    pub fn step_back(&mut self, n: usize) -> usize {
+        (0..n).take_while(|_| self.vm.step_back()).count()
+    }
+
+    /// undoes instructions until `executed` have run, returning `false` if the
+    /// history does not reach back that far
+    pub fn rewind_to(&mut self, executed: u64) -> bool {
+        self.vm.rewind_to(executed)
+    }
+
+    /// runs until a breakpoint or the end of the program
+    pub fn cont(&mut self) -> Stop {
+        self.resume(|_, _| false)
+    }
+
+    /// carries on with an interrupted command, if there is one
+    pub fn carry_on(&mut self) -> Stop {
+        match self.pending.take() {
+            Some(stop) => self.run_until(stop),
+            None => Stop::Step,
+        }
+    }
+
+    /// whether a command was interrupted before it finished
+    pub fn interrupted(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// forgets an interrupted command, leaving the program where it is
+    pub fn pause(&mut self) {
+        self.pending = None;
+    }
+
+    /// steps until `stop` holds after an instruction, a breakpoint is reached
+    /// or the program ends
+    fn resume(&mut self, stop: impl FnMut(&BasmVM, &Sequence) -> bool + 'static) -> Stop {
+        self.run_until(Box::new(stop))
+    }
+
+    fn run_until(&mut self, mut stop: Box<StopWhen>) -> Stop {
+        self.pending = None;
+        let here = (self.vm.rip, self.vm.executed);
+        let mut leaving = self.at_breakpoint.take() == Some(here);
+        let mut left = self.slice;
+        loop {
+            let rip = self.vm.rip;
+            if !std::mem::take(&mut leaving) {
+                if let Some(bp) = self.breakpoints.iter().find(|bp| bp.address == rip) {
+                    self.at_breakpoint = Some((rip, self.vm.executed));
+                    return Stop::Breakpoint(bp.id);
+                }
+            }
+            if left == Some(0) {
+                self.pending = Some(stop);
+                return Stop::Interrupted;
+            }
+            left = left.map(|left| left - 1);
+            let seq = decode_at(&self.vm.mem, rip);
+            match self.vm.step() {
+                StepEvent::Executed { .. } | StepEvent::Syscall { .. } => {
+                    if seq.is_some_and(|seq| stop(&self.vm, &seq)) {
+                        return Stop::Step;
+                    }
+                }
+                StepEvent::Exited(code) => return Stop::Exited(code),
+                StepEvent::End => return Stop::End,
+                StepEvent::Faulted(err) => return Stop::Faulted(err),
+            }
+        }
+    }
+
+    fn back(&mut self, n: usize, out: &mut impl Write) -> std::io::Result<()> {
+        match self.step_back(n) {
+            0 => writeln!(out, "no history to step back through"),
+            _ => self.location(out),
+        }
+    }
+
+    fn report(&self, stop: Stop, out: &mut impl Write) -> std::io::Result<()> {
+        match stop {
+            Stop::Step | Stop::Interrupted => (),
+            Stop::Breakpoint(id) => write!(out, "breakpoint {id}, ")?,
+            Stop::Exited(code) => return writeln!(out, "program exited with code {code}"),
+            Stop::End => return writeln!(out, "program ran past its last instruction"),
+            Stop::Faulted(err) => return write!(out, "{err}\n{}", self.backtrace()),
+        }
+        self.location(out)
+    }
+
+    /// a symbolic line for each frame, innermost first
+    pub fn backtrace(&self) -> String {
+        self.vm.symbols.backtrace(&self.frames())
+    }
+
+    fn location(&self, out: &mut impl Write) -> std::io::Result<()> {
+        let rip = self.vm.rip;
+        let at = self.vm.symbols.describe(rip);
+        let text = self
+            .vm
+            .symbols
+            .line(rip)
+            .and_then(|line| self.src.lines().nth(line as usize))
+            .map(|line| line.trim().to_string())
+            .or_else(|| decode_at(&self.vm.mem, rip).map(|seq| seq.to_string()));
+        match text {
+            Some(text) => writeln!(out, "at {rip:#06x} {at}: {text}"),
+            None => writeln!(out, "at {rip:#06x} {at}"),
+        }
+    }
+
+    fn print(&self, name: &str, out: &mut impl Write) -> std::io::Result<()> {
+        if let Some((reg, part)) = register(name) {
+            let val = self.vm.reg_part(reg, part);
+            return writeln!(out, "{name} = {val:#06x} ({val})");
+        }
+        let Some(var) = self.vm.symbols.variable(name) else {
+            return writeln!(out, "no register or variable `{name}`");
+        };
+        let Ok(bytes) = self.vm.slice(var.address, var.len) else {
+            return writeln!(out, "`{name}` lies outside of memory");
+        };
+        let text = String::from_utf8_lossy(bytes);
+        writeln!(
+            out,
+            "{name} @ {:#06x} = [{}] {text:?}",
+            var.address,
+            hex(bytes)
+        )
+    }
+
+    fn dump(&self, at: &str, len: Option<u16>, out: &mut impl Write) -> std::io::Result<()> {
+        let (address, var_len) = if let Some(var) = self.vm.symbols.variable(at) {
+            (var.address, Some(var.len))
+        } else if let Some((reg, part)) = register(at) {
+            (self.vm.reg_part(reg, part), None)
+        } else if let Some(address) = parse_number(at) {
+            (address, None)
+        } else {
+            return writeln!(out, "expected an address, register or variable, got `{at}`");
+        };
+        let to_end = (MEM_SIZE - address as usize).min(16) as u16;
+        let len = len.or(var_len).unwrap_or(to_end);
+        let Ok(bytes) = self.vm.slice(address, len) else {
+            return writeln!(out, "{address:#06x}..+{len} lies outside of memory");
+        };
+        for (i, row) in bytes.chunks(16).enumerate() {
+            let text: String = row
+                .iter()
+                .map(|&b| match b.is_ascii_graphic() || b == b' ' {
+                    true => b as char,
+                    false => '.',
+                })
+                .collect();
+            let row_address = address.wrapping_add(i as u16 * 16);
+            writeln!(out, "{row_address:#06x}: {:<47}  |{text}|", hex(row))?;
+        }
+        Ok(())
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<_> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    bytes.join(" ")
+}
+
+/// a decimal or `0x` prefixed hex number
+fn parse_number(s: &str) -> Option<u16> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u16::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
@@ -0,0 +1,81 @@
+use std::env::args;
+use std::io::{stdin, stdout, BufRead, BufReader, Write};
+use std::process::ExitCode;
+
+use basm_dbg::dap::DapServer;
+use basm_dbg::{Debugger, Flow};
+
+fn main() -> ExitCode {
+    if args().any(|s| s == "--dap") {
+        let mut server = DapServer::new(BufReader::new(stdin()), stdout());
+        return match server.run() {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let Some(path) = program_path() else {
+        eprintln!("usage: basm-dbg <program> [--commands <file>] | basm-dbg --dap");
+        return ExitCode::FAILURE;
+    };
+    let src = match std::fs::read_to_string(&path) {
+        Ok(src) => src,
+        Err(e) => {
+            eprintln!("unable to read {path}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut dbg = match Debugger::new(&src) {
+        Ok(dbg) => dbg,
+        Err(errs) => {
+            eprintln!("unable to load {path}:{errs}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let res = match args().skip_while(|s| s != "--commands").nth(1) {
+        Some(commands) => std::fs::read_to_string(commands)
+            .and_then(|script| dbg.run_script(&script, &mut stdout())),
+        None => interactive(&mut dbg),
+    };
+    match res {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn interactive(dbg: &mut Debugger) -> std::io::Result<()> {
+    let mut out = stdout();
+    dbg.execute("where", &mut out)?;
+    let mut line = String::new();
+    loop {
+        write!(out, "(basm) ")?;
+        out.flush()?;
+        line.clear();
+        if stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(());
+        }
+        if dbg.execute(line.trim(), &mut out)? == Flow::Quit {
+            return Ok(());
+        }
+    }
+}
+
+/// the first argument which is not a flag
+fn program_path() -> Option<String> {
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--commands" => {
+                args.next();
+            }
+            flag if flag.starts_with("--") => (),
+            _ => return Some(arg),
+        }
+    }
+    None
+}
@@ -0,0 +1,374 @@
+use expect_test::{expect, Expect};
+
+use basm_vm::io::{Captured, Scripted, VmIo};
+use basm_vm::BasmVM;
+
+use crate::Debugger;
+
+fn check(src: &str, script: &str, expect: Expect) {
+    let Ok(mut dbg) = Debugger::new(src) else {
+        panic!("failed to parse test program");
+    };
+    let stdout = Captured::default();
+    dbg.vm.io = VmIo::new(Scripted::default(), stdout.clone(), std::io::sink());
+    let mut out = Vec::new();
+    dbg.run_script(script, &mut out).unwrap();
+    let program = stdout.to_string_lossy();
+    let out = String::from_utf8_lossy(&out);
+    expect.assert_eq(&format!("{out}--- stdout\n{program}"));
+}
+
+const PROGRAM: &str = "
+    msg str \"hi!\", 10
+    count str 3
+
+_start:
+    mov rcx, 0
+loop:
+    call print
+    inc rcx
+    cmp rcx, 2
+    jne loop
+    mov rax, 60
+    mov rdi, 0
+    syscall
+
+print:
+    mov rax, 1
+    mov rdi, 1
+    mov rsi, msg
+    mov rdx, 4
+    syscall
+    ret
+";
+
+#[test]
+fn breakpoints() {
+    check(
+        PROGRAM,
+        "
+        where
+        break print
+        break 9
+        b nowhere
+        breakpoints
+        continue
+        c
+        delete 1
+        c
+        c
+        ",
+        expect![[r#"
+            (basm) where
+            at 0x000f _start (line 6): mov rcx, 0
+            (basm) break print
+            breakpoint 1 at 0x004f print (line 17)
+            (basm) break 9
+            breakpoint 2 at 0x001f loop+8 (line 9)
+            (basm) b nowhere
+            no instruction at `nowhere`
+            (basm) breakpoints
+            1: 0x004f print (line 17)
+            2: 0x001f loop+8 (line 9)
+            (basm) continue
+            breakpoint 1, at 0x004f print (line 17): mov rax, 1
+            (basm) c
+            breakpoint 2, at 0x001f loop+8 (line 9): inc rcx
+            (basm) delete 1
+            deleted breakpoint 1
+            (basm) c
+            breakpoint 2, at 0x001f loop+8 (line 9): inc rcx
+            (basm) c
+            program exited with code 0
+            --- stdout
+            hi!
+            hi!
+        "#]],
+    );
+}
+
+#[test]
+fn stepping() {
+    check(
+        PROGRAM,
+        "
+        step
+        next
+        p rcx
+        step 3
+        step
+        finish
+        s 2
+        finish
+        ",
+        expect![[r#"
+            (basm) step
+            at 0x0017 loop (line 8): call print
+            (basm) next
+            at 0x001f loop+8 (line 9): inc rcx
+            (basm) p rcx
+            rcx = 0x0000 (0)
+            (basm) step 3
+            at 0x0017 loop (line 8): call print
+            (basm) step
+            at 0x004f print (line 17): mov rax, 1
+            (basm) finish
+            at 0x001f loop+8 (line 9): inc rcx
+            (basm) s 2
+            at 0x002f loop+24 (line 11): jne loop
+            (basm) finish
+            program exited with code 0
+            --- stdout
+            hi!
+            hi!
+        "#]],
+    );
+}
+
+#[test]
+fn inspect() {
+    check(
+        PROGRAM,
+        "
+        b 8
+        c
+        regs
+        flags
+        p msg
+        p cl
+        p missing
+        x msg
+        x count 2
+        x rsi 6
+        x 0xfffa
+        bogus
+        ",
+        expect![[r#"
+            (basm) b 8
+            breakpoint 1 at 0x0017 loop (line 8)
+            (basm) c
+            breakpoint 1, at 0x0017 loop (line 8): call print
+            (basm) regs
+            rax: 0x0000 rbx: 0x0000 rcx: 0x0000 rdx: 0x0000
+            rsi: 0x0000 rdi: 0x0000 rsp: 0xffff rbp: 0x0000
+            r08: 0x0000 r09: 0x0000 r10: 0x0000 r11: 0x0000
+            r12: 0x0000 r13: 0x0000 r14: 0x0000 r15: 0x0000
+            rip: 0x0017 flags: []
+            (basm) flags
+            flags: []
+            (basm) p msg
+            msg @ 0x0008 = [68 69 21 0a] "hi!\n"
+            (basm) p cl
+            cl = 0x0000 (0)
+            (basm) p missing
+            no register or variable `missing`
+            (basm) x msg
+            0x0008: 68 69 21 0a                                      |hi!.|
+            (basm) x count 2
+            0x000e: 03 04                                            |..|
+            (basm) x rsi 6
+            0x0000: 0f 00 7f 00 0f 00                                |......|
+            (basm) x 0xfffa
+            0xfffa: 00 00 00 00 00 00                                |......|
+            (basm) bogus
+            unknown command `bogus`, try `help`
+            --- stdout
+        "#]],
+    );
+}
+
+#[test]
+fn restart_and_quit() {
+    check(
+        PROGRAM,
+        "
+        # comments are skipped
+        c
+        b print
+        run
+        quit
+        c
+        ",
+        expect![[r#"
+            (basm) c
+            program exited with code 0
+            (basm) b print
+            breakpoint 1 at 0x004f print (line 17)
+            (basm) run
+            breakpoint 1, at 0x004f print (line 17): mov rax, 1
+            (basm) quit
+            --- stdout
+            hi!
+            hi!
+        "#]],
+    );
+}
+
+#[test]
+fn entry_breakpoint() {
+    check(
+        PROGRAM,
+        "
+        b 6
+        c
+        c
+        run
+        s
+        ",
+        expect![[r#"
+            (basm) b 6
+            breakpoint 1 at 0x000f _start (line 6)
+            (basm) c
+            breakpoint 1, at 0x000f _start (line 6): mov rcx, 0
+            (basm) c
+            program exited with code 0
+            (basm) run
+            breakpoint 1, at 0x000f _start (line 6): mov rcx, 0
+            (basm) s
+            at 0x0017 loop (line 8): call print
+            --- stdout
+            hi!
+            hi!
+        "#]],
+    );
+}
+
+#[test]
+fn fault() {
+    check(
+        "
+_start:
+    mov rax, 1
+    pop rbx",
+        "
+        c
+        where
+        ",
+        expect![[r#"
+            (basm) c
+            fault at 0x000e: pop from an empty stack
+            rax: 0x0001 rbx: 0x0000 rcx: 0x0000 rdx: 0x0000
+            rsi: 0x0000 rdi: 0x0000 rsp: 0xffff rbp: 0x0000
+            r08: 0x0000 r09: 0x0000 r10: 0x0000 r11: 0x0000
+            r12: 0x0000 r13: 0x0000 r14: 0x0000 r15: 0x0000
+            rip: 0x000e flags: []
+            #0 0x000e _start+8 (line 4)
+            (basm) where
+            at 0x000e _start+8 (line 4): pop rbx
+            --- stdout
+        "#]],
+    );
+}
+
+#[test]
+fn step_back() {
+    check(
+        PROGRAM,
+        "
+        break print
+        c
+        back
+        s
+        finish
+        back
+        finish
+        p rcx
+        back 4
+        p rcx
+        rewind 99
+        rewind 1
+        back
+        back
+        c
+        ",
+        expect![[r#"
+            (basm) break print
+            breakpoint 1 at 0x004f print (line 17)
+            (basm) c
+            breakpoint 1, at 0x004f print (line 17): mov rax, 1
+            (basm) back
+            at 0x0017 loop (line 8): call print
+            (basm) s
+            at 0x004f print (line 17): mov rax, 1
+            (basm) finish
+            breakpoint 1, at 0x004f print (line 17): mov rax, 1
+            (basm) back
+            at 0x0017 loop (line 8): call print
+            (basm) finish
+            breakpoint 1, at 0x004f print (line 17): mov rax, 1
+            (basm) p rcx
+            rcx = 0x0000 (0)
+            (basm) back 4
+            at 0x000f _start (line 6): mov rcx, 0
+            (basm) p rcx
+            rcx = 0x0000 (0)
+            (basm) rewind 99
+            history does not reach instruction 99
+            (basm) rewind 1
+            history does not reach instruction 1
+            (basm) back
+            no history to step back through
+            (basm) back
+            no history to step back through
+            (basm) c
+            breakpoint 1, at 0x004f print (line 17): mov rax, 1
+            --- stdout
+        "#]],
+    );
+}
+
+#[test]
+fn save() {
+    let Ok(mut dbg) = Debugger::new(PROGRAM) else {
+        panic!("failed to parse test program");
+    };
+    dbg.vm.io = VmIo::new(std::io::empty(), std::io::sink(), std::io::sink());
+    let path = std::env::temp_dir().join(format!("basm-dbg-{}.json", std::process::id()));
+    let mut out = Vec::new();
+    let script = format!("b print\nc\nsave {}", path.display());
+    dbg.run_script(&script, &mut out).unwrap();
+    let restored = BasmVM::load_snapshot(&path, None);
+    std::fs::remove_file(&path).unwrap();
+    let Ok(restored) = restored else {
+        panic!("failed to load the snapshot");
+    };
+    assert_eq!(restored.registers(), dbg.vm.registers());
+    assert_eq!(restored.executed, dbg.vm.executed);
+    assert!(String::from_utf8_lossy(&out)
+        .ends_with(&format!("saved a snapshot to {}\n", path.display())));
+}
+
+#[test]
+fn backtrace() {
+    check(
+        PROGRAM,
+        "
+        bt
+        break print
+        c
+        s 2
+        backtrace
+        finish
+        bt
+        ",
+        expect![[r#"
+            (basm) bt
+            #0 0x000f _start (line 6)
+            (basm) break print
+            breakpoint 1 at 0x004f print (line 17)
+            (basm) c
+            breakpoint 1, at 0x004f print (line 17): mov rax, 1
+            (basm) s 2
+            at 0x005f print+16 (line 19): mov rsi, msg
+            (basm) backtrace
+            #0 0x005f print+16 (line 19)
+            #1 0x0017 loop (line 8)
+            (basm) finish
+            at 0x001f loop+8 (line 9): inc rcx
+            (basm) bt
+            #0 0x001f loop+8 (line 9)
+            --- stdout
+            hi!
+        "#]],
+    );
+}
@@ -81,7 +81,7 @@ impl Formatter<'_> {
     }
 
     fn fmt_line(&mut self, lex: &[Advance]) {
-        if lex.len() < 1 {
+        if lex.is_empty() {
             return;
         }
         if self.err.contains(&lex[0].line) {
@@ -244,14 +244,14 @@ pub fn fmt(
 pub fn apply_fmt(src: &str) -> String {
     fn create_fmt(src: &str) -> Vec<Edit> {
         use basm::parse::Parser;
-        let (basm, errors, lex) = Parser::recorded(&src).parse();
-        let mut fmt = fmt(&basm, &lex, &src, &errors, &Default::default());
+        let (basm, errors, lex) = Parser::recorded(src).parse();
+        let mut fmt = fmt(&basm, &lex, src, &errors, &Default::default());
         fmt.sort_unstable_by_key(|e| (e.line, e.span));
-        return fmt;
+        fmt
     }
     let mut out = String::with_capacity(src.len());
     let mut p = 0;
-    for mut e in create_fmt(&src) {
+    for mut e in create_fmt(src) {
         e.span.from += e.offset;
         e.span.to += e.offset;
         out.push_str(&src[p as usize..e.span.from as usize]);
@@ -18,7 +18,7 @@ fn display_edit(src: &str, edit: Edit) -> String {
 }
 
 fn check(src: &str, expect: Expect) {
-    let (basm, errors, lex) = Parser::recorded(&src).parse();
+    let (basm, errors, lex) = Parser::recorded(src).parse();
     expect.assert_eq(
         &crate::fmt(&basm, &lex, src, &errors, &Default::default())
             .into_iter()
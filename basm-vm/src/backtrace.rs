@@ -0,0 +1,31 @@
+use crate::{BasmVM, INS_SIZE};
+
+/// a call that has not yet returned, kept beside the program's own stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Frame {
+    /// the address of the call instruction
+    pub call: u16,
+    /// where on the stack the return address was pushed
+    pub slot: u16,
+    /// where the call went, the entry of the function called
+    pub target: u16,
+}
+
+impl Frame {
+    /// where the call should return to
+    pub fn return_address(&self) -> u16 {
+        self.call.wrapping_add(INS_SIZE)
+    }
+}
+
+impl BasmVM {
+    /// the calls that have not yet returned, outermost first
+    pub fn calls(&self) -> &[Frame] {
+        &self.calls
+    }
+    /// rip followed by the address of each call that led to it, innermost first
+    pub fn backtrace(&self) -> Vec<u16> {
+        let calls = self.calls.iter().rev().map(|frame| frame.call);
+        std::iter::once(self.rip).chain(calls).collect()
+    }
+}
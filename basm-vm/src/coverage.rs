@@ -0,0 +1,74 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use ahash::AHashMap;
+
+use crate::decode::decode_at;
+use crate::{BasmVM, Sequence, INS_SIZE};
+
+/// which instructions ran & which way each conditional jump went
+///
+/// counts build up over every run of the vm, so several runs with different
+/// input can be covered together
+#[derive(Debug, Clone, Default)]
+pub struct Coverage {
+    hits: AHashMap<u16, u64>,
+    /// how many times each conditional jump was taken & not taken
+    branches: AHashMap<u16, [u64; 2]>,
+}
+
+impl Coverage {
+    /// counts an instruction, and the way it went if it was a completed jump
+    pub(crate) fn record(&mut self, address: u16, seq: &Sequence, rip: Option<u16>) {
+        *self.hits.entry(address).or_default() += 1;
+        if let (true, Some(rip)) = (seq.is_conditional_jump(), rip) {
+            let taken = rip != address.wrapping_add(INS_SIZE);
+            self.branches.entry(address).or_default()[usize::from(!taken)] += 1;
+        }
+    }
+    /// how many times the instruction at `address` ran
+    pub fn hits(&self, address: u16) -> u64 {
+        self.hits.get(&address).copied().unwrap_or_default()
+    }
+    /// how many times the jump at `address` was taken & not taken
+    pub fn branch(&self, address: u16) -> [u64; 2] {
+        self.branches.get(&address).copied().unwrap_or_default()
+    }
+    /// an lcov tracefile for the program, naming its source `path`
+    pub fn lcov(&self, vm: &BasmVM, path: &str) -> String {
+        let symbols = &vm.symbols;
+        let mut lines = BTreeMap::<u32, u64>::new();
+        let mut branches = Vec::new();
+        for (i, &line) in symbols.lines.iter().enumerate() {
+            let address = symbols.code_start + i as u16 * INS_SIZE;
+            let line = line + 1;
+            let hits = self.hits(address);
+            let count = lines.entry(line).or_default();
+            *count = (*count).max(hits);
+            if decode_at(&vm.mem, address).is_some_and(|seq| seq.is_conditional_jump()) {
+                branches.push((line, i, hits, self.branch(address)));
+            }
+        }
+        let mut out = format!("TN:\nSF:{path}\n");
+        let mut hit = 0;
+        for &(line, block, hits, counts) in &branches {
+            for (branch, n) in counts.into_iter().enumerate() {
+                // a jump that never ran has no branches to count
+                let taken = match hits {
+                    0 => "-".to_string(),
+                    _ => n.to_string(),
+                };
+                let _ = writeln!(out, "BRDA:{line},{block},{branch},{taken}");
+                hit += usize::from(n > 0);
+            }
+        }
+        let _ = writeln!(out, "BRF:{}\nBRH:{hit}", branches.len() * 2);
+        for (line, n) in &lines {
+            let _ = writeln!(out, "DA:{line},{n}");
+        }
+        let found = lines.len();
+        let hit = lines.values().filter(|&&n| n > 0).count();
+        let _ = writeln!(out, "LF:{found}\nLH:{hit}\nend_of_record");
+        out
+    }
+}
@@ -1,4 +1,6 @@
-use crate::{Loc, LocKind, LocThenVal, Register, Sequence, Value};
+use crate::{
+    Loc, LocKind, LocThenVal, RegPart, Register, Sequence, Value, CODE_END, CODE_START, INS_SIZE,
+};
 
 #[derive(Debug, Clone, Copy)]
 struct SeqCode(u8);
@@ -23,16 +25,35 @@ impl SeqCode {
     }
 }
 
-pub fn decode(mem: &[u16]) -> impl Iterator<Item = Sequence> + '_ {
-    mem[mem[0] as usize..mem[1] as usize]
-        .chunks(4)
-        .filter_map(|word| {
-            let &[ins, v1, v2, _] = word else {
-                return None;
-            };
-            // println!("{ins:#06x} {v1:#06x} {v2:#06x}");
-            decode_seq(ins, v1, v2)
-        })
+pub fn decode(mem: &[u8]) -> impl Iterator<Item = Sequence> + '_ {
+    let start = read_word(mem, CODE_START) as usize;
+    let end = read_word(mem, CODE_END) as usize;
+    mem[start..end]
+        .chunks(INS_SIZE as usize)
+        .filter_map(decode_bytes)
+}
+
+/// decodes the instruction that starts at `address`
+pub fn decode_at(mem: &[u8], address: u16) -> Option<Sequence> {
+    let start = address as usize;
+    decode_bytes(mem.get(start..start + INS_SIZE as usize)?)
+}
+
+fn decode_bytes(bytes: &[u8]) -> Option<Sequence> {
+    let &[i0, i1, a0, a1, b0, b1, _, _] = bytes else {
+        return None;
+    };
+    let ins = u16::from_le_bytes([i0, i1]);
+    let v1 = u16::from_le_bytes([a0, a1]);
+    let v2 = u16::from_le_bytes([b0, b1]);
+    // println!("{ins:#06x} {v1:#06x} {v2:#06x}");
+    decode_seq(ins, v1, v2)
+}
+
+/// reads a little endian word from the memory header
+pub fn read_word(mem: &[u8], address: u16) -> u16 {
+    let at = address as usize;
+    u16::from_le_bytes([mem[at], mem[at + 1]])
 }
 
 fn loc(sq: &SeqCode, v: u16) -> Option<Loc> {
@@ -42,7 +63,10 @@ fn loc(sq: &SeqCode, v: u16) -> Option<Loc> {
     // println!("here! {sq:?} -> {v}");
     let loc = Loc {
         location: if sq.is_reg() {
-            LocKind::Reg(Register::try_from(v).ok()?)
+            LocKind::Reg(
+                Register::try_from(v & 0xff).ok()?,
+                RegPart::try_from(v >> 8).ok()?,
+            )
         } else {
             LocKind::Mem(v)
         },
@@ -68,7 +92,7 @@ pub fn decode_seq(ins: u16, v1: u16, v2: u16) -> Option<Sequence> {
     use Sequence::*;
     let sq = ins as u8;
     let sq1 = SeqCode(sq >> 4);
-    let sq2 = SeqCode(sq & 0x00ff);
+    let sq2 = SeqCode(sq & 0x0f);
     // println!("{v1:?} {v2:?}");
 
     Some(match ins >> 8 {
@@ -88,6 +112,26 @@ pub fn decode_seq(ins: u16, v1: u16, v2: u16) -> Option<Sequence> {
         0x0e => Cmp(value(&sq1, v1)?, value(&sq2, v2)?),
         0x0f => SysCall,
         0x10 => Ret,
+        0x11 => Jmp(loc(&sq2, v1)?),
+        0x12 => Jl(loc(&sq2, v1)?),
+        0x13 => Jle(loc(&sq2, v1)?),
+        0x14 => Jg(loc(&sq2, v1)?),
+        0x15 => Jge(loc(&sq2, v1)?),
+        0x16 => Jb(loc(&sq2, v1)?),
+        0x17 => Jbe(loc(&sq2, v1)?),
+        0x18 => Ja(loc(&sq2, v1)?),
+        0x19 => Jae(loc(&sq2, v1)?),
+        0x1a => Mul(value(&sq2, v1)?),
+        0x1b => Imul(value(&sq2, v1)?),
+        0x1c => Div(value(&sq2, v1)?),
+        0x1d => Idiv(value(&sq2, v1)?),
+        0x1e => Neg(loc(&sq2, v1)?),
+        0x1f => Not(loc(&sq2, v1)?),
+        0x20 => Shl(loc_then_val(&sq1, &sq2, v1, v2)?),
+        0x21 => Shr(loc_then_val(&sq1, &sq2, v1, v2)?),
+        0x22 => Sar(loc_then_val(&sq1, &sq2, v1, v2)?),
+        0x23 => Rol(loc_then_val(&sq1, &sq2, v1, v2)?),
+        0x24 => Ror(loc_then_val(&sq1, &sq2, v1, v2)?),
         _ => return None,
     })
 }
@@ -1,43 +1,64 @@
 use ahash::AHashMap;
 use string_interner::DefaultSymbol;
 
-use crate::{Code, Loc, LocKind, LocThenVal, Sequence, Value};
+use crate::symbols::{Symbols, Variable};
+use crate::{
+    Code, Loc, LocKind, LocThenVal, Sequence, Value, CODE_END, CODE_START, ENTRY, INS_SIZE,
+};
 
 // TODO: consider encoding length at the start of multi word vars
 
 #[derive(Debug, Default)]
 struct Encoder<'a> {
     var_address: AHashMap<DefaultSymbol, u16>,
-    mem: &'a mut [u16],
+    mem: &'a mut [u8],
     i: usize,
+    code_start: u16,
 }
 
 #[derive(Debug)]
 pub enum EncodeError {
-    MissingSymbol(DefaultSymbol),
+    /// a label or variable that is used but never defined, by name
+    MissingSymbol(String),
+    /// the program needs more bytes of memory than there are, leaving none
+    /// for the stack
+    OutOfMemory { needed: usize },
 }
 
 impl Encoder<'_> {
-    fn curr(&mut self) -> &mut [u16] {
+    fn curr(&mut self) -> &mut [u8] {
         &mut self.mem[self.i..]
     }
 
-    fn write(&mut self, words: &[u16]) {
-        self.curr()[..words.len()].copy_from_slice(words);
-        self.i += words.len();
+    fn write(&mut self, bytes: &[u8]) {
+        self.curr()[..bytes.len()].copy_from_slice(bytes);
+        self.i += bytes.len();
+    }
+
+    /// words are written little endian
+    fn write_words(&mut self, words: &[u16]) {
+        for word in words {
+            self.write(&word.to_le_bytes());
+        }
+    }
+
+    fn set_word(&mut self, address: u16, word: u16) {
+        let at = address as usize;
+        self.mem[at..at + 2].copy_from_slice(&word.to_le_bytes());
     }
 
     fn loc_address(&self, loc: Loc, code: &Code) -> Result<u16, EncodeError> {
         match loc.location {
-            LocKind::Reg(reg) => Ok(reg as u16),
+            LocKind::Reg(reg, part) => Ok((part as u16) << 8 | reg as u16),
             LocKind::Sym(mem) => {
-                if let Some(&address) = code.labels.get(&mem) {
-                    return Ok(address);
+                if let Some(&index) = code.labels.get(&mem) {
+                    return Ok(self.label_address(index));
                 }
                 if let Some(&address) = self.var_address.get(&mem) {
                     return Ok(address);
                 }
-                Err(EncodeError::MissingSymbol(mem))
+                let name = code.si.resolve(mem).unwrap_or_default();
+                Err(EncodeError::MissingSymbol(name.to_string()))
             }
             LocKind::Mem(add) => Ok(add),
         }
@@ -47,7 +68,7 @@ impl Encoder<'_> {
         match val {
             Value::Loc(loc) => self.loc_address(*loc, code),
             Value::Word(word) => Ok(*word),
-            Value::Words(words) => Ok(*words.get(0).unwrap_or(&0)),
+            Value::Words(words) => Ok(*words.first().unwrap_or(&0)),
         }
     }
 
@@ -60,8 +81,8 @@ impl Encoder<'_> {
 
     fn loc_code(&self, loc: &Loc) -> u8 {
         match loc.location {
-            LocKind::Reg(_) if loc.deref => 0x01,
-            LocKind::Reg(_) => 0x00,
+            LocKind::Reg(..) if loc.deref => 0x01,
+            LocKind::Reg(..) => 0x00,
             _ if loc.deref => 0x03,
             _ => 0x02,
         }
@@ -91,17 +112,20 @@ impl Encoder<'_> {
         use Sequence::*;
         Ok(match seq {
             SysCall | Ret => (0, [0; 3]),
-            Mov(vl) | Add(vl) | Sub(vl) | Xor(vl) | And(vl) | Or(vl) => {
+            Mov(vl) | Add(vl) | Sub(vl) | Xor(vl) | And(vl) | Or(vl) | Shl(vl) | Shr(vl)
+            | Sar(vl) | Rol(vl) | Ror(vl) => {
                 let [v1, v2] = self.loc_value_to_words(vl, code)?;
                 (self.loc_value_code(vl), [v1, v2, 0])
             }
-            Push(value) => {
+            Push(value) | Mul(value) | Imul(value) | Div(value) | Idiv(value) => {
                 let v = self.value_to_word(value, code)?;
-                (self.value_code(&value), [v, 0, 0])
+                (self.value_code(value), [v, 0, 0])
             }
-            Pop(loc) | Call(loc) | Je(loc) | Jne(loc) | Inc(loc) | Dec(loc) => {
+            Pop(loc) | Call(loc) | Inc(loc) | Dec(loc) | Jmp(loc) | Je(loc) | Jne(loc)
+            | Jl(loc) | Jle(loc) | Jg(loc) | Jge(loc) | Jb(loc) | Jbe(loc) | Ja(loc) | Jae(loc)
+            | Neg(loc) | Not(loc) => {
                 let v = self.loc_address(*loc, code)?;
-                (self.loc_code(&loc), [v, 0, 0])
+                (self.loc_code(loc), [v, 0, 0])
             }
             Cmp(v1, v2) => {
                 let w1 = self.value_to_word(v1, code)?;
@@ -111,40 +135,83 @@ impl Encoder<'_> {
         })
     }
 
-    fn encode(&mut self, code: Code) {
-        self.write(&[0, 0]);
-        for (name, words) in &code.variables {
-            self.write(&[words.len() as u16]);
+    /// converts a label's sequence index into its address in memory
+    fn label_address(&self, index: u16) -> u16 {
+        self.code_start + index * INS_SIZE
+    }
+
+    fn encode(&mut self, code: Code) -> Result<Symbols, Vec<EncodeError>> {
+        let mut symbols = Symbols::default();
+        self.write_words(&[0, 0, 0]);
+        // in order of first mention, so that addresses are the same every time
+        let mut variables: Vec<_> = code.variables.iter().collect();
+        variables.sort_by_key(|(name, _)| **name);
+        for (name, bytes) in variables {
+            self.write_words(&[bytes.len() as u16]);
             self.var_address.insert(*name, self.i as u16);
-            self.write(&words);
+            symbols.variables.push(Variable {
+                name: code.si.resolve(*name).unwrap_or_default().to_string(),
+                address: self.i as u16,
+                len: bytes.len() as u16,
+            });
+            self.write(bytes);
         }
-        self.mem[0] = self.i as u16;
+        self.code_start = self.i as u16;
+        self.set_word(CODE_START, self.code_start);
+        let entry = code
+            .si
+            .get("_start")
+            .and_then(|start| code.labels.get(&start))
+            .map_or(self.code_start, |&index| self.label_address(index));
+        self.set_word(ENTRY, entry);
+        symbols.code_start = self.code_start;
+        symbols.lines = code.lines.clone();
+        symbols.labels = code
+            .labels
+            .iter()
+            .map(|(name, &index)| {
+                let name = code.si.resolve(*name).unwrap_or_default().to_string();
+                (name, self.label_address(index))
+            })
+            .collect();
+        symbols.labels.sort_by(|a, b| (a.1, &a.0).cmp(&(b.1, &b.0)));
+        let mut errors = Vec::new();
         for seq in &code.sequences {
-            let (code, vals) = match self.seq_code_and_values(seq, &code) {
-                Ok(v) => v,
-                Err(e) => {
-                    println!("{e:?}");
-                    continue;
-                }
-            };
+            // the instruction still takes its place, keeping later addresses right
+            let (code, vals) = self.seq_code_and_values(seq, &code).unwrap_or_else(|e| {
+                errors.push(e);
+                (0, [0; 3])
+            });
             let words = [
                 u16::from_le_bytes([code, seq.code()]),
                 vals[0],
                 vals[1],
                 vals[2],
             ];
-            self.write(&words);
+            self.write_words(&words);
+        }
+        self.set_word(CODE_END, self.i as u16);
+        match errors.is_empty() {
+            true => Ok(symbols),
+            false => Err(errors),
         }
-        self.mem[1] = self.i as u16;
     }
 }
 
-pub fn encode(code: Code, mem: &mut [u16]) -> usize {
+/// writes the program into memory, returning where its symbols ended up
+pub fn encode(code: Code, mem: &mut [u8]) -> Result<Symbols, Vec<EncodeError>> {
+    // the header, each variable with its length & each instruction
+    let needed = 3 * 2
+        + code.variables.values().map(|v| 2 + v.len()).sum::<usize>()
+        + code.sequences.len() * INS_SIZE as usize;
+    // every address within the image, and its end, must fit in a word
+    if needed >= mem.len().min(u16::MAX as usize) {
+        return Err(vec![EncodeError::OutOfMemory { needed }]);
+    }
     let mut enc = Encoder {
         mem,
         i: 0,
         ..Default::default()
     };
-    enc.encode(code);
-    enc.i
+    enc.encode(code)
 }
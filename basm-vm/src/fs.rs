@@ -0,0 +1,387 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Component, Path, PathBuf};
+
+use ahash::AHashMap;
+use serde_json::{json, Value};
+
+use crate::io::{EACCES, EBADF, EEXIST, EINVAL, EIO, EMFILE, ENOENT};
+use crate::snapshot::{field, hex, uint, unhex, SnapshotError};
+
+pub const O_RDONLY: u16 = 0o0;
+pub const O_WRONLY: u16 = 0o1;
+pub const O_RDWR: u16 = 0o2;
+pub const O_CREAT: u16 = 0o100;
+pub const O_EXCL: u16 = 0o200;
+pub const O_TRUNC: u16 = 0o1000;
+pub const O_APPEND: u16 = 0o2000;
+
+/// the first fd handed out to a file, after stdin, stdout & stderr
+pub const FIRST_FD: u16 = 3;
+/// the most files a program may have open at once
+pub const MAX_OPEN: usize = 64;
+
+/// a linux errno value
+pub type Errno = u16;
+
+/// the files a program can see, which never reach outside of the sandbox
+#[derive(Debug)]
+pub struct Vfs {
+    root: VfsRoot,
+    /// the open files, indexed by `fd - FIRST_FD`
+    fds: Vec<Option<OpenFile>>,
+}
+
+#[derive(Debug)]
+pub enum VfsRoot {
+    /// files kept in memory, keyed by their normalised path
+    Memory(AHashMap<String, Vec<u8>>),
+    /// files within a directory of the host
+    Host(PathBuf),
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    kind: OpenKind,
+    read: bool,
+    write: bool,
+    append: bool,
+}
+
+#[derive(Debug)]
+enum OpenKind {
+    Memory { path: String, pos: usize },
+    Host { path: String, file: File },
+}
+
+impl Default for Vfs {
+    fn default() -> Self {
+        Self::memory()
+    }
+}
+
+impl Vfs {
+    /// an empty in memory filesystem
+    pub fn memory() -> Self {
+        Self::new(VfsRoot::Memory(AHashMap::new()))
+    }
+    /// a filesystem rooted in `root` on the host
+    pub fn host(root: impl Into<PathBuf>) -> Self {
+        Self::new(VfsRoot::Host(root.into()))
+    }
+    pub fn new(root: VfsRoot) -> Self {
+        Self {
+            root,
+            fds: Vec::new(),
+        }
+    }
+    pub fn root(&self) -> &VfsRoot {
+        &self.root
+    }
+    /// adds or replaces a file, returning `false` if the path leaves the sandbox
+    pub fn insert(&mut self, path: &str, contents: impl Into<Vec<u8>>) -> bool {
+        let Some(path) = normalise(path) else {
+            return false;
+        };
+        match &mut self.root {
+            VfsRoot::Memory(files) => {
+                files.insert(path, contents.into());
+                true
+            }
+            VfsRoot::Host(root) => host_path(root, &path)
+                .is_ok_and(|path| std::fs::write(path, contents.into()).is_ok()),
+        }
+    }
+    /// the contents of a file, if it exists
+    pub fn contents(&self, path: &str) -> Option<Vec<u8>> {
+        let path = normalise(path)?;
+        match &self.root {
+            VfsRoot::Memory(files) => files.get(&path).cloned(),
+            VfsRoot::Host(root) => std::fs::read(host_path(root, &path).ok()?).ok(),
+        }
+    }
+    /// whether `fd` refers to an open file
+    pub fn is_open(&self, fd: u16) -> bool {
+        self.file(fd).is_ok()
+    }
+    /// closes every open file, starting a fresh fd table
+    pub fn close_all(&mut self) {
+        self.fds.clear();
+    }
+
+    /// opens a file with linux `open` flags, returning its fd
+    pub fn open(&mut self, path: &str, flags: u16) -> Result<u16, Errno> {
+        let path = normalise(path).ok_or(EACCES)?;
+        let (read, write) = match flags & 0o3 {
+            O_RDONLY => (true, false),
+            O_WRONLY => (false, true),
+            O_RDWR => (true, true),
+            _ => return Err(EINVAL),
+        };
+        let (create, excl) = (flags & O_CREAT != 0, flags & O_EXCL != 0);
+        let truncate = write && flags & O_TRUNC != 0;
+        let kind = match &mut self.root {
+            VfsRoot::Memory(files) => {
+                let exists = files.contains_key(&path);
+                match (exists, create, excl) {
+                    (true, true, true) => return Err(EEXIST),
+                    (false, false, _) => return Err(ENOENT),
+                    _ => (),
+                }
+                let file = files.entry(path.clone()).or_default();
+                if truncate {
+                    file.clear();
+                }
+                OpenKind::Memory { path, pos: 0 }
+            }
+            VfsRoot::Host(root) => OpenOptions::new()
+                .read(read)
+                .write(write)
+                .create(create && !excl)
+                .create_new(create && excl)
+                .truncate(truncate)
+                .open(host_path(root, &path)?)
+                .map(|file| OpenKind::Host { path, file })
+                .map_err(errno)?,
+        };
+        let file = OpenFile {
+            kind,
+            read,
+            write,
+            append: flags & O_APPEND != 0,
+        };
+        let slot = match self.fds.iter().position(Option::is_none) {
+            Some(slot) => slot,
+            None if self.fds.len() < MAX_OPEN => {
+                self.fds.push(None);
+                self.fds.len() - 1
+            }
+            None => return Err(EMFILE),
+        };
+        self.fds[slot] = Some(file);
+        Ok(FIRST_FD + slot as u16)
+    }
+    /// reads from the current position of `fd`, returning the bytes read
+    pub fn read(&mut self, fd: u16, buf: &mut [u8]) -> Result<u16, Errno> {
+        let Vfs { root, fds } = self;
+        let file = file_mut(fds, fd)?;
+        if !file.read {
+            return Err(EBADF);
+        }
+        let count = match (&mut file.kind, root) {
+            (OpenKind::Memory { path, pos }, VfsRoot::Memory(files)) => {
+                let data = files.get(path.as_str()).map_or(&[][..], |d| d);
+                let rest = data.get(*pos..).unwrap_or_default();
+                let count = rest.len().min(buf.len());
+                buf[..count].copy_from_slice(&rest[..count]);
+                *pos += count;
+                count
+            }
+            (OpenKind::Host { file: f, .. }, _) => f.read(buf).map_err(errno)?,
+            _ => return Err(EIO),
+        };
+        Ok(count as u16)
+    }
+    /// writes to the current position of `fd`, returning the bytes written
+    pub fn write(&mut self, fd: u16, bytes: &[u8]) -> Result<u16, Errno> {
+        let Vfs { root, fds } = self;
+        let file = file_mut(fds, fd)?;
+        if !file.write {
+            return Err(EBADF);
+        }
+        match (&mut file.kind, root) {
+            (OpenKind::Memory { path, pos }, VfsRoot::Memory(files)) => {
+                let data = files.entry(path.clone()).or_default();
+                if file.append {
+                    *pos = data.len();
+                }
+                let end = *pos + bytes.len();
+                if data.len() < end {
+                    data.resize(end, 0);
+                }
+                data[*pos..end].copy_from_slice(bytes);
+                *pos = end;
+            }
+            (OpenKind::Host { file: f, .. }, _) => {
+                if file.append {
+                    f.seek(SeekFrom::End(0)).map_err(errno)?;
+                }
+                f.write_all(bytes).map_err(errno)?;
+            }
+            _ => return Err(EIO),
+        }
+        Ok(bytes.len() as u16)
+    }
+    pub fn close(&mut self, fd: u16) -> Result<(), Errno> {
+        self.file(fd)?;
+        self.fds[(fd - FIRST_FD) as usize] = None;
+        while let Some(None) = self.fds.last() {
+            self.fds.pop();
+        }
+        Ok(())
+    }
+
+    /// the root & open files, with the contents of an in memory root
+    ///
+    /// a host root is only marked, as the directory is for whoever restores it
+    /// to choose
+    pub(crate) fn to_json(&self) -> Value {
+        let root = match &self.root {
+            VfsRoot::Memory(files) => {
+                let mut files: Vec<_> = files.iter().collect();
+                files.sort();
+                let files: serde_json::Map<_, _> = files
+                    .into_iter()
+                    .map(|(path, data)| (path.clone(), json!(hex(data))))
+                    .collect();
+                json!({ "memory": files })
+            }
+            VfsRoot::Host(_) => json!({ "host": true }),
+        };
+        let fds: Vec<_> = (0..self.fds.len() as u16)
+            .map(|i| i + FIRST_FD)
+            .filter_map(|fd| Some((fd, self.file(fd).ok()?)))
+            .map(|(fd, file)| {
+                let (path, pos) = match &file.kind {
+                    OpenKind::Memory { path, pos } => (path, *pos as u64),
+                    OpenKind::Host { path, file } => {
+                        (path, (&*file).stream_position().unwrap_or_default())
+                    }
+                };
+                json!({
+                    "fd": fd,
+                    "path": path,
+                    "pos": pos,
+                    "read": file.read,
+                    "write": file.write,
+                    "append": file.append,
+                })
+            })
+            .collect();
+        json!({ "root": root, "fds": fds })
+    }
+    /// rebuilds a filesystem from [`Vfs::to_json`], reopening its files
+    ///
+    /// a host root is placed at `host`, and every path must stay within it
+    pub(crate) fn from_json(value: &Value, host: Option<&Path>) -> Result<Self, SnapshotError> {
+        let root = field(value, "root")?;
+        let root = match (root.get("memory"), root.get("host")) {
+            (Some(Value::Object(files)), _) => {
+                let files = files
+                    .iter()
+                    .map(|(path, data)| {
+                        let bad = || SnapshotError::Field(format!("fs.{path}"));
+                        let data = data.as_str().and_then(unhex).ok_or_else(bad)?;
+                        Ok((normalise(path).ok_or_else(bad)?, data))
+                    })
+                    .collect::<Result<_, SnapshotError>>()?;
+                VfsRoot::Memory(files)
+            }
+            (_, Some(_)) => VfsRoot::Host(host.ok_or(SnapshotError::HostRoot)?.into()),
+            _ => return Err(SnapshotError::Field("fs.root".to_string())),
+        };
+        let mut vfs = Vfs::new(root);
+        let fds = field(value, "fds")?.as_array();
+        for fd in fds.ok_or_else(|| SnapshotError::Field("fs.fds".to_string()))? {
+            let bad = || SnapshotError::Field("fs.fds".to_string());
+            let flag = |name| {
+                field(fd, name)
+                    .ok()
+                    .and_then(Value::as_bool)
+                    .ok_or_else(bad)
+            };
+            let (read, write, append) = (flag("read")?, flag("write")?, flag("append")?);
+            let path = field(fd, "path")?.as_str().and_then(normalise);
+            let path = path.ok_or_else(bad)?;
+            let pos = uint(fd, "pos")?;
+            let slot = uint(fd, "fd")?
+                .checked_sub(FIRST_FD as u64)
+                .filter(|&slot| slot < MAX_OPEN as u64)
+                .ok_or_else(bad)? as usize;
+            let kind = match &vfs.root {
+                VfsRoot::Memory(_) => OpenKind::Memory {
+                    path,
+                    pos: pos as usize,
+                },
+                VfsRoot::Host(root) => {
+                    let mut file = OpenOptions::new()
+                        .read(read)
+                        .write(write)
+                        .open(host_path(root, &path).map_err(|_| bad())?)?;
+                    file.seek(SeekFrom::Start(pos))?;
+                    OpenKind::Host { path, file }
+                }
+            };
+            if vfs.fds.len() <= slot {
+                vfs.fds.resize_with(slot + 1, || None);
+            }
+            vfs.fds[slot] = Some(OpenFile {
+                kind,
+                read,
+                write,
+                append,
+            });
+        }
+        Ok(vfs)
+    }
+
+    fn file(&self, fd: u16) -> Result<&OpenFile, Errno> {
+        fd.checked_sub(FIRST_FD)
+            .and_then(|i| self.fds.get(i as usize))
+            .and_then(Option::as_ref)
+            .ok_or(EBADF)
+    }
+}
+
+fn file_mut(fds: &mut [Option<OpenFile>], fd: u16) -> Result<&mut OpenFile, Errno> {
+    fd.checked_sub(FIRST_FD)
+        .and_then(|i| fds.get_mut(i as usize))
+        .and_then(Option::as_mut)
+        .ok_or(EBADF)
+}
+
+/// a relative path without `..`, so it cannot leave the root
+fn normalise(path: &str) -> Option<String> {
+    let mut parts = Vec::new();
+    for component in Path::new(path).components() {
+        match component {
+            Component::Normal(part) => parts.push(part.to_str()?),
+            Component::CurDir => (),
+            _ => return None,
+        }
+    }
+    (!parts.is_empty()).then(|| parts.join("/"))
+}
+
+/// where a normalised path lies on the host, so long as no symlink takes it
+/// out of the root
+fn host_path(root: &Path, path: &str) -> Result<PathBuf, Errno> {
+    let root = root.canonicalize().map_err(errno)?;
+    let joined = root.join(path);
+    let resolved = match joined.symlink_metadata() {
+        // a dangling link fails here, rather than being created through
+        Ok(_) => joined.canonicalize().map_err(|_| EACCES)?,
+        // a file yet to be created is checked by the directory it goes in
+        Err(_) => {
+            let parent = joined.parent().ok_or(EACCES)?.canonicalize();
+            parent
+                .map_err(errno)?
+                .join(joined.file_name().ok_or(EACCES)?)
+        }
+    };
+    match resolved.starts_with(&root) {
+        true => Ok(resolved),
+        false => Err(EACCES),
+    }
+}
+
+fn errno(err: std::io::Error) -> Errno {
+    use std::io::ErrorKind::*;
+    match err.kind() {
+        NotFound => ENOENT,
+        PermissionDenied => EACCES,
+        AlreadyExists => EEXIST,
+        InvalidInput => EINVAL,
+        _ => EIO,
+    }
+}
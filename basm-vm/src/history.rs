@@ -0,0 +1,81 @@
+use crate::backtrace::Frame;
+use crate::trace::MemWrite;
+use crate::BasmVM;
+
+/// what is needed to undo a single instruction
+#[derive(Debug, Clone)]
+pub(crate) struct Undo {
+    pub rip: u16,
+    pub flag: u16,
+    /// registers that changed, by index, along with their old values
+    pub regs: Vec<(usize, u16)>,
+    pub writes: Vec<MemWrite>,
+    pub calls: CallChange,
+    /// where the replay stood, as a syscall moves it on
+    pub replay: Option<usize>,
+}
+
+/// how an instruction changed the calls that have not yet returned
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum CallChange {
+    None,
+    Called,
+    Returned(Frame),
+}
+
+impl BasmVM {
+    /// undoes the last instruction, returning `false` if there is no history left
+    ///
+    /// only the registers, flags, memory & replay are restored, anything the
+    /// program wrote to a stream or file stays written
+    pub fn step_back(&mut self) -> bool {
+        let Some(undo) = self.history.pop_back() else {
+            return false;
+        };
+        for write in undo.writes.iter().rev() {
+            let at = write.address as usize;
+            self.mem[at..at + write.old.len()].copy_from_slice(&write.old);
+        }
+        for (i, val) in undo.regs {
+            self.reg[i] = val;
+        }
+        match undo.calls {
+            CallChange::None => (),
+            CallChange::Called => {
+                self.calls.pop();
+            }
+            CallChange::Returned(frame) => self.calls.push(frame),
+        }
+        if let (Some(replay), Some(position)) = (&mut self.replay, undo.replay) {
+            replay.rewind(position);
+        }
+        self.rip = undo.rip;
+        self.flag = undo.flag;
+        self.executed -= 1;
+        self.halted = None;
+        true
+    }
+    /// steps back until `executed` instructions have run, returning `false`
+    /// if the history does not reach back that far, or `executed` is ahead
+    pub fn rewind_to(&mut self, executed: u64) -> bool {
+        match self.executed.checked_sub(executed) {
+            Some(n) if n <= self.history.len() as u64 => (),
+            _ => return false,
+        }
+        while self.executed > executed {
+            self.step_back();
+        }
+        true
+    }
+    /// the number of instructions that can be stepped back
+    pub fn history_len(&self) -> usize {
+        self.history.len()
+    }
+
+    pub(crate) fn remember(&mut self, undo: Undo) {
+        self.history.push_back(undo);
+        while self.history.len() > self.options.history {
+            self.history.pop_front();
+        }
+    }
+}
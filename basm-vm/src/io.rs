@@ -0,0 +1,142 @@
+use std::collections::VecDeque;
+use std::io::{Read, Write};
+use std::sync::{Arc, Mutex};
+
+use crate::fs::Errno;
+
+/// linux errno values, negated in rax when a syscall fails
+pub const ENOENT: Errno = 2;
+pub const EIO: Errno = 5;
+pub const EBADF: Errno = 9;
+pub const EACCES: Errno = 13;
+pub const EEXIST: Errno = 17;
+pub const EINVAL: Errno = 22;
+pub const EMFILE: Errno = 24;
+
+/// the standard streams of a program, on fds 0, 1 & 2
+pub struct VmIo {
+    pub stdin: Box<dyn Read + Send>,
+    pub stdout: Box<dyn Write + Send>,
+    pub stderr: Box<dyn Write + Send>,
+}
+
+impl Default for VmIo {
+    fn default() -> Self {
+        Self::new(std::io::stdin(), std::io::stdout(), std::io::stderr())
+    }
+}
+
+impl std::fmt::Debug for VmIo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VmIo").finish_non_exhaustive()
+    }
+}
+
+impl VmIo {
+    pub fn new(
+        stdin: impl Read + Send + 'static,
+        stdout: impl Write + Send + 'static,
+        stderr: impl Write + Send + 'static,
+    ) -> Self {
+        Self {
+            stdin: Box::new(stdin),
+            stdout: Box::new(stdout),
+            stderr: Box::new(stderr),
+        }
+    }
+
+    /// reads what is available on `fd`, returning the bytes read
+    ///
+    /// like a terminal, this may return less than asked for, e.g. a single line
+    pub fn read(&mut self, fd: u16, buf: &mut [u8]) -> Result<u16, Errno> {
+        if fd != 0 {
+            return Err(EBADF);
+        }
+        loop {
+            match self.stdin.read(buf) {
+                Ok(read) => return Ok(read as u16),
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(_) => return Err(EIO),
+            }
+        }
+    }
+    /// the output stream behind `fd`, if any
+    pub fn output(&mut self, fd: u16) -> Option<&mut (dyn Write + Send)> {
+        match fd {
+            1 => Some(&mut *self.stdout),
+            2 => Some(&mut *self.stderr),
+            _ => None,
+        }
+    }
+
+    /// writes all of `bytes` to `fd`, returning the bytes written
+    pub fn write(&mut self, fd: u16, bytes: &[u8]) -> Result<u16, Errno> {
+        let out = self.output(fd).ok_or(EBADF)?;
+        match out.write_all(bytes).and_then(|_| out.flush()) {
+            Ok(()) => Ok(bytes.len() as u16),
+            Err(_) => Err(EIO),
+        }
+    }
+}
+
+/// an in memory output stream that can be read while the vm holds it
+#[derive(Debug, Clone, Default)]
+pub struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    pub fn contents(&self) -> Vec<u8> {
+        self.0.lock().map(|b| b.clone()).unwrap_or_default()
+    }
+    pub fn to_string_lossy(&self) -> String {
+        String::from_utf8_lossy(&self.contents()).into_owned()
+    }
+    /// empties the buffer, returning what it held
+    pub fn take(&self) -> Vec<u8> {
+        self.0
+            .lock()
+            .map(|mut b| std::mem::take(&mut *b))
+            .unwrap_or_default()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0
+            .lock()
+            .map_err(|_| std::io::Error::other("poisoned output"))?
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// input typed ahead of time, handed out one chunk per read as a terminal would
+#[derive(Debug, Clone, Default)]
+pub struct Scripted(VecDeque<Vec<u8>>);
+
+impl Scripted {
+    /// each line is returned by its own read, once everything before it is read
+    pub fn lines<I: IntoIterator<Item = S>, S: Into<Vec<u8>>>(lines: I) -> Self {
+        Self(lines.into_iter().map(Into::into).collect())
+    }
+    pub fn push(&mut self, line: impl Into<Vec<u8>>) {
+        self.0.push_back(line.into());
+    }
+}
+
+impl Read for Scripted {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let Some(line) = self.0.front_mut() else {
+            return Ok(0);
+        };
+        let count = line.len().min(buf.len());
+        buf[..count].copy_from_slice(&line[..count]);
+        line.drain(..count);
+        if line.is_empty() {
+            self.0.pop_front();
+        }
+        Ok(count)
+    }
+}
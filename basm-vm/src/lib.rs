@@ -1,4 +1,6 @@
+use std::collections::VecDeque;
 use std::process::ExitCode;
+use std::time::{Duration, Instant};
 
 use ahash::{AHashMap, AHashSet};
 use string_interner::symbol::SymbolU32;
@@ -6,23 +8,98 @@ use string_interner::{DefaultBackend, DefaultSymbol, StringInterner};
 
 use basm::{parse::ParseError, Address};
 
+use self::backtrace::Frame;
+use self::coverage::Coverage;
 use self::encode::EncodeError;
+use self::fs::Vfs;
+use self::history::{CallChange, Undo};
+use self::io::VmIo;
+use self::profile::Profiler;
 use self::reparse::{reparse, ReparseError};
+use self::replay::Replay;
+use self::symbols::Symbols;
+use self::syscall::{SyscallHandler, SyscallResult, Syscalls};
+use self::trace::{MemWrite, TraceRecord, Tracer};
 
+pub mod backtrace;
+pub mod coverage;
 pub mod decode;
 pub mod encode;
+pub mod fs;
+mod history;
+pub mod io;
+pub mod profile;
 pub mod reparse;
+pub mod replay;
+pub mod snapshot;
+pub mod symbols;
+pub mod syscall;
+pub mod trace;
+
+#[cfg(test)]
+mod test;
 
 // TODO: parse values within decoder
 
 #[derive(Debug)]
 pub struct BasmVM {
     pub flag: u16,
+    /// instruction pointer, the address of the next instruction
+    pub rip: u16,
     pub reg: [u16; REGISTER_COUNT],
-    pub mem: [u16; MEM_SIZE],
+    /// the number of instructions executed by the current run
+    pub executed: u64,
+    pub mem: [u8; MEM_SIZE],
+    pub options: VmOptions,
+    /// where the program's labels, variables & lines are in memory
+    pub symbols: Symbols,
+    pub io: VmIo,
+    /// the files the program can open
+    pub fs: Vfs,
+    pub syscalls: Syscalls,
+    /// sees every instruction executed
+    pub tracer: Option<Box<dyn Tracer>>,
+    /// counts the instructions executed, when set
+    pub profiler: Option<Profiler>,
+    /// records which instructions & branches ran, when set
+    pub coverage: Option<Coverage>,
+    /// records syscall results, or replays them, when set
+    pub replay: Option<Replay>,
+    /// when the current run must end by
+    deadline: Option<Instant>,
+    /// how the program stopped, once it has
+    halted: Option<StepEvent>,
+    /// the memory writes of the current instruction, while they are being recorded
+    journal: Option<Vec<MemWrite>>,
+    /// undo records of the latest instructions, oldest first
+    history: VecDeque<Undo>,
+    /// the calls that have not yet returned, outermost first
+    calls: Vec<Frame>,
+    /// memory as the program was loaded, which each run starts from
+    image: Box<[u8]>,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct VmOptions {
+    /// fault on signed overflow rather than wrapping around
+    ///
+    /// default = `false`
+    pub trap_overflow: bool,
+    /// the most instructions a run may execute
+    ///
+    /// default = `None`, no limit
+    pub max_instructions: Option<u64>,
+    /// the longest a run may take on the wall clock
+    ///
+    /// default = `None`, no limit
+    pub time_limit: Option<Duration>,
+    /// how many instructions can be undone with [`BasmVM::step_back`]
+    ///
+    /// default = `0`, no history is kept
+    pub history: usize,
 }
 
-type VariableMap = AHashMap<DefaultSymbol, Box<[u16]>>;
+type VariableMap = AHashMap<DefaultSymbol, Box<[u8]>>;
 type GlobalMap = AHashSet<DefaultSymbol>;
 type LabelMap = AHashMap<DefaultSymbol, Address>;
 
@@ -30,6 +107,8 @@ type LabelMap = AHashMap<DefaultSymbol, Address>;
 pub struct Code {
     pub si: StringInterner<DefaultBackend>,
     pub sequences: Vec<Sequence>,
+    /// the source line of each sequence, counting from zero
+    pub lines: Vec<u32>,
     pub variables: VariableMap,
     pub globals: GlobalMap,
     pub labels: LabelMap,
@@ -37,7 +116,7 @@ pub struct Code {
 
 // NOTE: need to load variables into memory early.
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Sequence {
     /// Does nothing
     Mov(LocThenVal),
@@ -46,6 +125,12 @@ pub enum Sequence {
     Xor(LocThenVal),
     And(LocThenVal),
     Or(LocThenVal),
+    Shl(LocThenVal),
+    Shr(LocThenVal),
+    /// arithmetic shift right, keeping the sign bit
+    Sar(LocThenVal),
+    Rol(LocThenVal),
+    Ror(LocThenVal),
     Push(Value),
     Pop(Loc),
     Call(Loc),
@@ -56,9 +141,44 @@ pub enum Sequence {
     Cmp(Value, Value),
     SysCall,
     Ret,
+    Jmp(Loc),
+    /// signed less than
+    Jl(Loc),
+    /// signed less than or equal
+    Jle(Loc),
+    /// signed greater than
+    Jg(Loc),
+    /// signed greater than or equal
+    Jge(Loc),
+    /// unsigned less than
+    Jb(Loc),
+    /// unsigned less than or equal
+    Jbe(Loc),
+    /// unsigned greater than
+    Ja(Loc),
+    /// unsigned greater than or equal
+    Jae(Loc),
+    /// unsigned `rdx:rax = rax * value`
+    Mul(Value),
+    /// signed `rdx:rax = rax * value`
+    Imul(Value),
+    /// unsigned `rdx:rax / value`, quotient in rax & remainder in rdx
+    Div(Value),
+    /// signed `rdx:rax / value`, quotient in rax & remainder in rdx
+    Idiv(Value),
+    Neg(Loc),
+    Not(Loc),
 }
 
 impl Sequence {
+    /// whether this is a jump taken only on some flags
+    pub fn is_conditional_jump(&self) -> bool {
+        use Sequence::*;
+        matches!(
+            self,
+            Je(_) | Jne(_) | Jl(_) | Jle(_) | Jg(_) | Jge(_) | Jb(_) | Jbe(_) | Ja(_) | Jae(_)
+        )
+    }
     fn code(&self) -> u8 {
         use Sequence::*;
         match self {
@@ -78,13 +198,85 @@ impl Sequence {
             Cmp(_, _) => 0x0e,
             SysCall => 0x0f,
             Ret => 0x10,
+            Jmp(_) => 0x11,
+            Jl(_) => 0x12,
+            Jle(_) => 0x13,
+            Jg(_) => 0x14,
+            Jge(_) => 0x15,
+            Jb(_) => 0x16,
+            Jbe(_) => 0x17,
+            Ja(_) => 0x18,
+            Jae(_) => 0x19,
+            Mul(_) => 0x1a,
+            Imul(_) => 0x1b,
+            Div(_) => 0x1c,
+            Idiv(_) => 0x1d,
+            Neg(_) => 0x1e,
+            Not(_) => 0x1f,
+            Shl(_) => 0x20,
+            Shr(_) => 0x21,
+            Sar(_) => 0x22,
+            Rol(_) => 0x23,
+            Ror(_) => 0x24,
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct LocThenVal(pub Loc, pub Value);
 
+/// disassembles the sequence, with addresses in place of symbols
+impl std::fmt::Display for Sequence {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Sequence::*;
+        let (name, operands) = match self {
+            Mov(vl) => ("mov", vl.to_string()),
+            Add(vl) => ("add", vl.to_string()),
+            Sub(vl) => ("sub", vl.to_string()),
+            Xor(vl) => ("xor", vl.to_string()),
+            And(vl) => ("and", vl.to_string()),
+            Or(vl) => ("or", vl.to_string()),
+            Shl(vl) => ("shl", vl.to_string()),
+            Shr(vl) => ("shr", vl.to_string()),
+            Sar(vl) => ("sar", vl.to_string()),
+            Rol(vl) => ("rol", vl.to_string()),
+            Ror(vl) => ("ror", vl.to_string()),
+            Push(val) => ("push", val.to_string()),
+            Pop(loc) => ("pop", loc.to_string()),
+            Call(loc) => ("call", loc.to_string()),
+            Je(loc) => ("je", loc.to_string()),
+            Jne(loc) => ("jne", loc.to_string()),
+            Inc(loc) => ("inc", loc.to_string()),
+            Dec(loc) => ("dec", loc.to_string()),
+            Cmp(v1, v2) => ("cmp", format!("{v1}, {v2}")),
+            SysCall => return write!(f, "syscall"),
+            Ret => return write!(f, "ret"),
+            Jmp(loc) => ("jmp", loc.to_string()),
+            Jl(loc) => ("jl", loc.to_string()),
+            Jle(loc) => ("jle", loc.to_string()),
+            Jg(loc) => ("jg", loc.to_string()),
+            Jge(loc) => ("jge", loc.to_string()),
+            Jb(loc) => ("jb", loc.to_string()),
+            Jbe(loc) => ("jbe", loc.to_string()),
+            Ja(loc) => ("ja", loc.to_string()),
+            Jae(loc) => ("jae", loc.to_string()),
+            Mul(val) => ("mul", val.to_string()),
+            Imul(val) => ("imul", val.to_string()),
+            Div(val) => ("div", val.to_string()),
+            Idiv(val) => ("idiv", val.to_string()),
+            Neg(loc) => ("neg", loc.to_string()),
+            Not(loc) => ("not", loc.to_string()),
+        };
+        write!(f, "{name} {operands}")
+    }
+}
+
+impl std::fmt::Display for LocThenVal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}, {}", self.0, self.1)
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Value {
     Loc(Loc),
@@ -92,24 +284,296 @@ pub enum Value {
     Words(Box<[u16]>),
 }
 
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Loc(loc) => write!(f, "{loc}"),
+            Value::Word(word) => write!(f, "{word}"),
+            Value::Words(words) => {
+                let words: Vec<_> = words.iter().map(u16::to_string).collect();
+                write!(f, "{}", words.join(", "))
+            }
+        }
+    }
+}
+
+impl LocThenVal {
+    /// the width of the operation, taken from the first register operand
+    pub fn width(&self) -> Width {
+        self.0.width().or(self.1.width()).unwrap_or(Width::Word)
+    }
+}
+
+impl Value {
+    pub fn width(&self) -> Option<Width> {
+        match self {
+            Value::Loc(loc) => loc.width(),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Loc {
     pub location: LocKind,
     pub deref: bool,
 }
+
+impl Loc {
+    /// the width of a register, memory has no width of its own
+    pub fn width(&self) -> Option<Width> {
+        match self.location {
+            LocKind::Reg(_, part) if !self.deref => Some(part.width()),
+            _ => None,
+        }
+    }
+    fn width_or_word(&self) -> Width {
+        self.width().unwrap_or(Width::Word)
+    }
+}
+
+impl std::fmt::Display for Loc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let location = match self.location {
+            LocKind::Mem(address) => format!("{address:#06x}"),
+            LocKind::Reg(reg, part) => reg.name(part),
+            LocKind::Sym(sym) => format!("{sym:?}"),
+        };
+        match self.deref {
+            true => write!(f, "[{location}]"),
+            false => write!(f, "{location}"),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub enum LocKind {
     Mem(Address),
-    Reg(Register),
+    Reg(Register, RegPart),
     Sym(SymbolU32),
 }
 
+/// a fault raised by the program while it is running
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// the instruction pointer does not point at a valid instruction
+    IllegalInstruction,
+    BadSyscall(u16),
+    /// an access of memory that goes past its end, holding the address accessed
+    MemoryOutOfRange(u16),
+    /// the stack has grown into the program's code
+    StackOverflow,
+    /// a pop of an empty stack
+    StackUnderflow,
+    DivisionByZero,
+    /// the quotient of a division does not fit within rax
+    DivisionOverflow,
+    /// signed overflow while [`VmOptions::trap_overflow`] is set
+    Overflow,
+    /// the run hit one of its limits, not a fault of the program itself
+    BudgetExhausted(Budget),
+    /// a replayed program made a syscall other than the one recorded next,
+    /// `expected` is `None` past the end of the recording
+    ReplayDiverged {
+        expected: Option<u16>,
+        found: u16,
+    },
+    /// a `ret` that would not go back to its call, `unbalanced` holds how many
+    /// more bytes are on the stack than after the call, zero if the return
+    /// address itself was overwritten
+    CorruptReturn {
+        expected: u16,
+        found: u16,
+        unbalanced: i16,
+    },
+}
+
+/// a limit placed on a run through [`VmOptions`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Budget {
+    Instructions,
+    Time,
+}
+
+impl std::fmt::Display for FaultKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use FaultKind::*;
+        match self {
+            IllegalInstruction => write!(f, "illegal instruction"),
+            BadSyscall(code) => write!(f, "unknown syscall {code:#x}"),
+            MemoryOutOfRange(address) => write!(f, "memory access out of range at {address:#06x}"),
+            StackOverflow => write!(f, "stack overflow"),
+            StackUnderflow => write!(f, "pop from an empty stack"),
+            DivisionByZero => write!(f, "division by zero"),
+            DivisionOverflow => write!(f, "quotient too large"),
+            Overflow => write!(f, "arithmetic overflow"),
+            BudgetExhausted(Budget::Instructions) => write!(f, "instruction budget exhausted"),
+            BudgetExhausted(Budget::Time) => write!(f, "time limit reached"),
+            ReplayDiverged {
+                expected: Some(expected),
+                found,
+            } => write!(
+                f,
+                "replay diverged, syscall {found:#x} where {expected:#x} was recorded"
+            ),
+            ReplayDiverged {
+                expected: None,
+                found,
+            } => write!(
+                f,
+                "replay diverged, syscall {found:#x} past the end of the recording"
+            ),
+            CorruptReturn {
+                expected,
+                found,
+                unbalanced: 0,
+            } => write!(
+                f,
+                "return to {found:#06x} rather than {expected:#06x}, the return address was overwritten"
+            ),
+            CorruptReturn {
+                expected,
+                found,
+                unbalanced,
+            } => write!(
+                f,
+                "return to {found:#06x} rather than {expected:#06x}, the stack holds {unbalanced} bytes more than after the call"
+            ),
+        }
+    }
+}
+
+/// what happened during a single [`BasmVM::step`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StepEvent {
+    /// an instruction other than a syscall ran
+    Executed {
+        address: u16,
+    },
+    /// a syscall ran and the program carries on
+    Syscall {
+        address: u16,
+        number: u16,
+    },
+    /// the program exited through a syscall
+    Exited(u8),
+    /// the program ran past its last instruction
+    End,
+    Faulted(RuntimeError),
+}
+
+/// how a run ended, along with the final state of the registers
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// the code the program exited with, `None` if it was stopped
+    pub exit_code: Option<u8>,
+    /// the number of instructions executed
+    pub executed: u64,
+    pub reason: Termination,
+    pub registers: Registers,
+}
+
+/// why a run ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Termination {
+    /// the program exited through a syscall
+    Exited,
+    /// the program ran past its last instruction
+    End,
+    /// the run hit a limit, leaving rip on the instruction it would have run next
+    BudgetExhausted(Budget),
+    Faulted(RuntimeError),
+}
+
+impl RunOutcome {
+    pub fn error(&self) -> Option<&RuntimeError> {
+        match &self.reason {
+            Termination::Faulted(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<RunOutcome> for ExitCode {
+    fn from(outcome: RunOutcome) -> Self {
+        outcome.exit_code.map_or(ExitCode::FAILURE, ExitCode::from)
+    }
+}
+
+impl std::fmt::Display for Termination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Termination::Exited => write!(f, "exited"),
+            Termination::End => write!(f, "ran past the last instruction"),
+            Termination::BudgetExhausted(budget) => {
+                write!(f, "{}", FaultKind::BudgetExhausted(*budget))
+            }
+            Termination::Faulted(err) => write!(f, "{err}"),
+        }
+    }
+}
+
+/// the state of the registers at a point in time
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Registers {
+    pub reg: [u16; REGISTER_COUNT],
+    pub rip: u16,
+    pub flag: u16,
+}
+
+impl Registers {
+    pub fn flag(&self, flag: Flag) -> bool {
+        (self.flag & flag as u16) != 0
+    }
+}
+
+impl std::fmt::Display for Registers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, val) in self.reg.iter().enumerate() {
+            let reg = Register::try_from(i as u16).map_err(|_| std::fmt::Error)?;
+            let sep = if i % 4 == 3 { "\n" } else { " " };
+            write!(f, "{reg}: {val:#06x}{sep}")?;
+        }
+        let flags: Vec<_> = Flag::ALL.into_iter().filter(|&fl| self.flag(fl)).collect();
+        write!(f, "rip: {:#06x} flags: {flags:?}", self.rip)
+    }
+}
+
+/// a fault along with where it happened
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeError {
+    pub kind: FaultKind,
+    /// the address of the faulting instruction
+    pub address: u16,
+    pub registers: Registers,
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "fault at {:#06x}: {}", self.address, self.kind)?;
+        write!(f, "{}", self.registers)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
+
 pub enum VmError {
     ParseError(Vec<ParseError>),
     ReparseError(Vec<ReparseError>),
     EncodeError(Vec<EncodeError>),
 }
 
+/// each error in turn, each but the parse errors on a line of its own
+impl std::fmt::Display for VmError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParseError(errs) => errs.iter().try_for_each(|err| write!(f, "{err}")),
+            Self::ReparseError(errs) => errs.iter().try_for_each(|err| write!(f, "\n{err:?}")),
+            Self::EncodeError(errs) => errs.iter().try_for_each(|err| write!(f, "\n{err:?}")),
+        }
+    }
+}
+
 impl BasmVM {
     pub fn parse(src: &str) -> Result<Self, VmError> {
         let (code, err) = reparse(src);
@@ -119,137 +583,772 @@ impl BasmVM {
             _ => (),
         }
 
-        let reg = [0; REGISTER_COUNT];
         let mut mem = [0; MEM_SIZE];
-        encode::encode(code, &mut mem);
-        Ok(Self { flag: 0, reg, mem })
-    }
-    pub fn run(&mut self) -> ExitCode {
-        self.set_reg(Register::RSP, u16::MAX);
-        for seq in decode::decode(&self.mem).collect::<Vec<_>>() {
-            use Sequence::*;
-            #[allow(unused)]
-            match seq {
-                Mov(LocThenVal(loc, val)) => *self.loc_mut(loc) = self.value(val),
-                Add(LocThenVal(loc, val)) => *self.loc_mut(loc) += self.value(val),
-                Sub(LocThenVal(loc, val)) => *self.loc_mut(loc) -= self.value(val),
-                Xor(LocThenVal(loc, val)) => *self.loc_mut(loc) ^= self.value(val),
-                And(LocThenVal(loc, val)) => *self.loc_mut(loc) &= self.value(val),
-                Or(LocThenVal(loc, val)) => *self.loc_mut(loc) |= self.value(val),
-                Push(val) => {
-                    *self.reg_mut(Register::RSP) -= 1;
-                    *self.mem_mut(self.reg(Register::RSP)) = self.value(val);
-                    // println!(
-                    //     "{} {:#06x}",
-                    //     self.reg(Register::RSP),
-                    //     self.mem(self.reg(Register::RSP))
-                    // );
+        let symbols = encode::encode(code, &mut mem).map_err(VmError::EncodeError)?;
+        Ok(Self::new(mem, symbols))
+    }
+    /// a vm over already encoded memory
+    fn new(mem: [u8; MEM_SIZE], symbols: Symbols) -> Self {
+        Self {
+            flag: 0,
+            rip: 0,
+            reg: [0; REGISTER_COUNT],
+            executed: 0,
+            mem,
+            options: VmOptions::default(),
+            symbols,
+            io: VmIo::default(),
+            fs: Vfs::default(),
+            syscalls: Syscalls::default(),
+            tracer: None,
+            deadline: None,
+            halted: None,
+            profiler: None,
+            coverage: None,
+            replay: None,
+            journal: None,
+            history: VecDeque::new(),
+            calls: Vec::new(),
+            image: Box::new(mem),
+        }
+    }
+    /// replaces the standard streams, which default to the host's
+    pub fn with_io(mut self, io: VmIo) -> Self {
+        self.io = io;
+        self
+    }
+    /// replaces the filesystem, which defaults to an empty one in memory
+    pub fn with_fs(mut self, fs: Vfs) -> Self {
+        self.fs = fs;
+        self
+    }
+    pub fn with_tracer(mut self, tracer: impl Tracer + 'static) -> Self {
+        self.tracer = Some(Box::new(tracer));
+        self
+    }
+    pub fn with_profiler(mut self) -> Self {
+        self.profiler = Some(Profiler::default());
+        self
+    }
+    pub fn with_coverage(mut self) -> Self {
+        self.coverage = Some(Coverage::default());
+        self
+    }
+    /// adds a syscall on top of the default linux table
+    pub fn with_syscall(mut self, number: u16, handler: impl SyscallHandler + 'static) -> Self {
+        self.syscalls.register(number, handler);
+        self
+    }
+    pub fn run(&mut self) -> RunOutcome {
+        self.reset();
+        self.resume()
+    }
+    /// runs on from where the vm is, without going back to the entry
+    ///
+    /// any time limit counts from here
+    pub fn resume(&mut self) -> RunOutcome {
+        self.deadline = self.options.time_limit.map(|limit| Instant::now() + limit);
+        let (exit_code, reason) = loop {
+            match self.step() {
+                StepEvent::Executed { .. } | StepEvent::Syscall { .. } => (),
+                StepEvent::Exited(code) => break (Some(code), Termination::Exited),
+                StepEvent::End => break (Some(0), Termination::End),
+                StepEvent::Faulted(RuntimeError {
+                    kind: FaultKind::BudgetExhausted(budget),
+                    ..
+                }) => break (None, Termination::BudgetExhausted(budget)),
+                StepEvent::Faulted(err) => break (None, Termination::Faulted(err)),
+            }
+        };
+        RunOutcome {
+            exit_code,
+            executed: self.executed,
+            reason,
+            registers: self.registers(),
+        }
+    }
+
+    /// readies the vm to run from the program's entry
+    ///
+    /// memory goes back to how it was loaded, and the registers & flags are
+    /// cleared
+    pub fn reset(&mut self) {
+        self.mem.copy_from_slice(&self.image);
+        self.reg = [0; REGISTER_COUNT];
+        self.flag = 0;
+        self.set_reg(Register::RSP, STACK_TOP);
+        self.fs.close_all();
+        self.executed = 0;
+        self.halted = None;
+        self.history.clear();
+        self.calls.clear();
+        if let Some(replay) = &mut self.replay {
+            replay.restart();
+        }
+        self.rip = decode::read_word(&self.mem, ENTRY);
+        if let Some(profiler) = &mut self.profiler {
+            profiler.start(self.rip);
+        }
+        self.deadline = self.options.time_limit.map(|limit| Instant::now() + limit);
+    }
+
+    /// executes the instruction at rip
+    ///
+    /// once the program has stopped, every further step returns the same event
+    pub fn step(&mut self) -> StepEvent {
+        if let Some(event) = &self.halted {
+            return event.clone();
+        }
+        let address = self.rip;
+        // jumps are checked, so only falling through the last instruction or
+        // jumping to a label after it gets here
+        if address == decode::read_word(&self.mem, CODE_END) {
+            return self.halt(StepEvent::End);
+        }
+        let mut syscall = None;
+        let res = self
+            .check_budget()
+            .and_then(|_| self.fetch())
+            .and_then(|seq| {
+                if let Sequence::SysCall = seq {
+                    syscall = Some(self.reg(Register::RAX));
+                }
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.record(address, &self.calls);
                 }
-                Pop(loc) => {
-                    *self.loc_mut(loc) = self.mem(self.reg(Register::RSP));
-                    *self.reg_mut(Register::RSP) += 1;
-                    // println!(
-                    //     "{} {:#06x}",
-                    //     self.reg(Register::RSP),
-                    //     self.mem(self.reg(Register::RSP))
-                    // );
+                let recording =
+                    self.tracer.is_some() || self.coverage.is_some() || self.options.history > 0;
+                let before = recording.then(|| {
+                    self.journal = Some(Vec::new());
+                    let replay = self.replay.as_ref().map(Replay::position);
+                    (
+                        self.registers(),
+                        seq.clone(),
+                        self.calls.last().copied(),
+                        replay,
+                    )
+                });
+                self.executed += 1;
+                self.rip = self.rip.wrapping_add(INS_SIZE);
+                let res = self.execute(seq);
+                if let Some((before, seq, top, replay)) = before {
+                    self.record(address, &seq, before, top, replay, res.is_ok());
                 }
-                Call(loc) => (),
-                Je(loc) => (),
-                Jne(loc) => (),
-                Inc(loc) => (),
-                Dec(loc) => (),
-                Cmp(v1, v2) => (),
-                SysCall => {
-                    match self.reg(Register::RAX) {
-                        // sys_write
-                        0x01 => {
-                            let fd = self.reg(Register::RDI);
-                            let buf = self.reg(Register::RSI);
-                            let count = self.reg(Register::RDX);
-                            let start = buf as usize;
-                            let end = start + count as usize / 2;
-
-                            let bytes: Vec<_> = self.mem[start..end]
-                                .iter()
-                                .flat_map(|&w| [(w >> 8) as u8, w as u8])
-                                .chain((count % 2 != 0).then(|| self.mem(end as u16) as u8))
-                                .collect();
-                            print!("{}", String::from_utf8_lossy(&bytes));
-                        }
-                        // sys_exit
-                        0x3C => {
-                            return ExitCode::from(self.reg(Register::RDI) as u8);
-                        }
-                        code => panic!("sys call not handled yet: {code}"),
+                res
+            });
+        match (res, syscall) {
+            (Ok(Some(code)), _) => self.halt(StepEvent::Exited(code)),
+            (Ok(None), Some(number)) => StepEvent::Syscall { address, number },
+            (Ok(None), None) => StepEvent::Executed { address },
+            (Err(kind), _) => {
+                // like x86, a fault leaves rip on the faulting instruction
+                self.rip = address;
+                let err = RuntimeError {
+                    kind,
+                    address,
+                    registers: self.registers(),
+                };
+                self.halt(StepEvent::Faulted(err))
+            }
+        }
+    }
+
+    /// passes what an instruction did on to the tracer, coverage & history
+    ///
+    /// `top` is the innermost call & `replay` the replay's position before the
+    /// instruction ran
+    fn record(
+        &mut self,
+        address: u16,
+        seq: &Sequence,
+        mut before: Registers,
+        top: Option<Frame>,
+        replay: Option<usize>,
+        ok: bool,
+    ) {
+        let writes = self.journal.take().unwrap_or_default();
+        if let Some(coverage) = &mut self.coverage {
+            coverage.record(address, seq, ok.then_some(self.rip));
+        }
+        if self.tracer.is_some() {
+            let after = self.registers();
+            let record =
+                TraceRecord::new(self.executed, address, seq, &before, &after, writes.clone());
+            if let Some(tracer) = &mut self.tracer {
+                tracer.trace(&record);
+            }
+        }
+        if self.options.history > 0 {
+            // a fault leaves rip where it was, so it is restored from the address
+            before.rip = address;
+            let regs = (0..REGISTER_COUNT)
+                .filter(|&i| before.reg[i] != self.reg[i])
+                .map(|i| (i, before.reg[i]))
+                .collect();
+            let calls = match (seq, ok, top) {
+                (Sequence::Call(_), true, _) => CallChange::Called,
+                (Sequence::Ret, true, Some(frame)) => CallChange::Returned(frame),
+                _ => CallChange::None,
+            };
+            self.remember(Undo {
+                rip: before.rip,
+                flag: before.flag,
+                regs,
+                writes,
+                calls,
+                replay,
+            });
+        }
+    }
+
+    fn halt(&mut self, event: StepEvent) -> StepEvent {
+        self.halted = Some(event.clone());
+        event
+    }
+
+    /// checks the limits of the run before the next instruction
+    fn check_budget(&self) -> Result<(), FaultKind> {
+        if self
+            .options
+            .max_instructions
+            .is_some_and(|max| self.executed >= max)
+        {
+            return Err(FaultKind::BudgetExhausted(Budget::Instructions));
+        }
+        // reading the clock is slow, so only do so every so often
+        let check_time = self.executed.is_multiple_of(1024);
+        if check_time
+            && self
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return Err(FaultKind::BudgetExhausted(Budget::Time));
+        }
+        Ok(())
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            reg: self.reg,
+            rip: self.rip,
+            flag: self.flag,
+        }
+    }
+
+    /// decodes the instruction at rip, which must lie on an instruction within the code
+    fn fetch(&self) -> Result<Sequence, FaultKind> {
+        self.check_target(self.rip)?;
+        decode::decode_at(&self.mem, self.rip).ok_or(FaultKind::IllegalInstruction)
+    }
+    /// faults unless `target` is the start of one of the program's instructions,
+    /// or the end of the code where a trailing label points
+    fn check_target(&self, target: u16) -> Result<(), FaultKind> {
+        let code_start = decode::read_word(&self.mem, CODE_START);
+        let code_end = decode::read_word(&self.mem, CODE_END);
+        match (code_start..=code_end).contains(&target)
+            && (target - code_start).is_multiple_of(INS_SIZE)
+        {
+            true => Ok(()),
+            false => Err(FaultKind::IllegalInstruction),
+        }
+    }
+
+    /// executes a single sequence, returning an exit code if the program exited
+    fn execute(&mut self, seq: Sequence) -> Result<Option<u8>, FaultKind> {
+        use Sequence::*;
+        match seq {
+            Mov(vl) => {
+                let w = vl.width();
+                let LocThenVal(loc, val) = vl;
+                let val = self.value(val, w)?;
+                self.set_loc(loc, val, w)?;
+            }
+            Add(vl) => self.try_binary(vl, |vm, a, b, w| {
+                let res = vm.add(a, b, w);
+                vm.trap_overflow(res)
+            })?,
+            Sub(vl) => self.try_binary(vl, |vm, a, b, w| {
+                let res = vm.sub(a, b, w);
+                vm.trap_overflow(res)
+            })?,
+            Xor(vl) => self.binary(vl, |vm, a, b, w| vm.logic(a ^ b, w))?,
+            And(vl) => self.binary(vl, |vm, a, b, w| vm.logic(a & b, w))?,
+            Or(vl) => self.binary(vl, |vm, a, b, w| vm.logic(a | b, w))?,
+            Shl(vl) => self.binary(vl, |vm, a, b, w| vm.shl(a, shift_count(b), w))?,
+            Shr(vl) => self.binary(vl, |vm, a, b, w| vm.shr(a, shift_count(b), w))?,
+            Sar(vl) => self.binary(vl, |vm, a, b, w| vm.sar(a, shift_count(b), w))?,
+            Rol(vl) => self.binary(vl, |vm, a, b, w| vm.rol(a, shift_count(b), w))?,
+            Ror(vl) => self.binary(vl, |vm, a, b, w| vm.ror(a, shift_count(b), w))?,
+            Push(val) => {
+                let val = self.value(val, Width::Word)?;
+                self.push(val)?;
+            }
+            Pop(loc) => {
+                let val = self.pop()?;
+                self.set_loc(loc, val, Width::Word)?;
+            }
+            Call(loc) => {
+                let call = self.rip.wrapping_sub(INS_SIZE);
+                let target = self.loc(loc, Width::Word)?;
+                self.check_target(target)?;
+                self.push(self.rip)?;
+                self.rip = target;
+                let slot = self.reg(Register::RSP);
+                self.calls.push(Frame { call, slot, target });
+            }
+            Je(loc) => self.jump_if(loc, self.flag(Flag::Zf))?,
+            Jne(loc) => self.jump_if(loc, !self.flag(Flag::Zf))?,
+            Inc(loc) => {
+                let w = loc.width_or_word();
+                self.try_update(loc, w, |vm, a| {
+                    let res = vm.keep_carry(|vm| vm.add(a, 1, w));
+                    vm.trap_overflow(res)
+                })?;
+            }
+            Dec(loc) => {
+                let w = loc.width_or_word();
+                self.try_update(loc, w, |vm, a| {
+                    let res = vm.keep_carry(|vm| vm.sub(a, 1, w));
+                    vm.trap_overflow(res)
+                })?;
+            }
+            Cmp(v1, v2) => {
+                let w = v1.width().or(v2.width()).unwrap_or(Width::Word);
+                let (a, b) = (self.value(v1, w)?, self.value(v2, w)?);
+                self.sub(a, b, w);
+            }
+            SysCall => match self.syscall() {
+                SyscallResult::Continue => (),
+                SyscallResult::Exit(code) => return Ok(Some(code)),
+                SyscallResult::Fault(kind) => return Err(kind),
+            },
+            Ret => {
+                let rsp = self.reg(Register::RSP);
+                // checked before popping, so a fault leaves the stack as it was
+                let found = self.peek()?;
+                // a ret without a call is left alone, it may be used as a jump
+                if let Some(frame) = self.calls.last() {
+                    let expected = frame.return_address();
+                    if found != expected || rsp != frame.slot {
+                        return Err(FaultKind::CorruptReturn {
+                            expected,
+                            found,
+                            unbalanced: frame.slot.wrapping_sub(rsp) as i16,
+                        });
                     }
                 }
-                Ret => (),
+                self.check_target(found)?;
+                self.set_reg(Register::RSP, rsp + 2);
+                self.calls.pop();
+                self.rip = found;
             }
+            Jmp(loc) => self.jump_if(loc, true)?,
+            Jl(loc) => self.jump_if(loc, self.flag(Flag::Sf) != self.flag(Flag::Of))?,
+            Jle(loc) => self.jump_if(
+                loc,
+                self.flag(Flag::Zf) || self.flag(Flag::Sf) != self.flag(Flag::Of),
+            )?,
+            Jg(loc) => self.jump_if(
+                loc,
+                !self.flag(Flag::Zf) && self.flag(Flag::Sf) == self.flag(Flag::Of),
+            )?,
+            Jge(loc) => self.jump_if(loc, self.flag(Flag::Sf) == self.flag(Flag::Of))?,
+            Jb(loc) => self.jump_if(loc, self.flag(Flag::Cf))?,
+            Jbe(loc) => self.jump_if(loc, self.flag(Flag::Cf) || self.flag(Flag::Zf))?,
+            Ja(loc) => self.jump_if(loc, !self.flag(Flag::Cf) && !self.flag(Flag::Zf))?,
+            Jae(loc) => self.jump_if(loc, !self.flag(Flag::Cf))?,
+            Mul(val) => {
+                let w = val.width().unwrap_or(Width::Word);
+                let b = self.value(val, w)? & w.mask();
+                let res = (self.reg(Register::RAX) & w.mask()) as u32 * b as u32;
+                self.set_wide(w, res);
+                self.set_flag(Flag::Cf, res >> w.bits() != 0);
+                self.set_flag(Flag::Of, res >> w.bits() != 0);
+            }
+            Imul(val) => {
+                let w = val.width().unwrap_or(Width::Word);
+                let b = self.value(val, w)?;
+                let res = w.sign_extend(self.reg(Register::RAX)) * w.sign_extend(b);
+                self.set_wide(w, res as u32);
+                let overflow = res != w.sign_extend(res as u16);
+                self.set_flag(Flag::Cf, overflow);
+                self.set_flag(Flag::Of, overflow);
+            }
+            Div(val) => {
+                let w = val.width().unwrap_or(Width::Word);
+                let dividend = self.wide(w);
+                let divisor = (self.value(val, w)? & w.mask()) as u32;
+                if divisor == 0 {
+                    return Err(FaultKind::DivisionByZero);
+                }
+                let quotient = dividend / divisor;
+                if quotient > w.mask() as u32 {
+                    return Err(FaultKind::DivisionOverflow);
+                }
+                self.set_quotient(w, quotient as u16, (dividend % divisor) as u16);
+            }
+            Idiv(val) => {
+                let w = val.width().unwrap_or(Width::Word);
+                let dividend = match w {
+                    Width::Byte => self.wide(w) as u16 as i16 as i32,
+                    Width::Word => self.wide(w) as i32,
+                };
+                let divisor = w.sign_extend(self.value(val, w)?);
+                if divisor == 0 {
+                    return Err(FaultKind::DivisionByZero);
+                }
+                let quotient = dividend
+                    .checked_div(divisor)
+                    .filter(|&q| q == w.sign_extend(q as u16))
+                    .ok_or(FaultKind::DivisionOverflow)?;
+                self.set_quotient(w, quotient as u16, (dividend % divisor) as u16);
+            }
+            Neg(loc) => {
+                let w = loc.width_or_word();
+                self.try_update(loc, w, |vm, a| {
+                    let res = vm.sub(0, a, w);
+                    vm.trap_overflow(res)
+                })?;
+            }
+            Not(loc) => self.update(loc, loc.width_or_word(), |_, a| !a)?,
         }
-        ExitCode::default()
+        Ok(None)
     }
 
-    // fn flag(&self, flag: Flag) -> bool {
-    //     (self.flag & flag as u16) != 0
-    // }
-    // fn set_flag(&mut self, flag: Flag, set: bool) {
-    //     if set {
-    //         self.flag |= flag as u16;
-    //     } else {
-    //         self.flag &= !(flag as u16);
-    //     }
-    // }
-    fn reg(&self, reg: Register) -> u16 {
-        self.reg[reg as usize]
+    /// the stack grows down from [`STACK_TOP`] and may not run into the code
+    fn push(&mut self, val: u16) -> Result<(), FaultKind> {
+        let rsp = self.reg(Register::RSP);
+        let code_end = decode::read_word(&self.mem, CODE_END);
+        let rsp = rsp
+            .checked_sub(2)
+            .filter(|&rsp| rsp >= code_end)
+            .ok_or(FaultKind::StackOverflow)?;
+        self.set_reg(Register::RSP, rsp);
+        self.store(rsp, val, Width::Word)
+    }
+    fn pop(&mut self) -> Result<u16, FaultKind> {
+        let val = self.peek()?;
+        let rsp = self.reg(Register::RSP);
+        self.set_reg(Register::RSP, rsp + 2);
+        Ok(val)
+    }
+    /// the word on top of the stack, leaving it there
+    fn peek(&self) -> Result<u16, FaultKind> {
+        let rsp = self.reg(Register::RSP);
+        if rsp > STACK_TOP - 2 {
+            return Err(FaultKind::StackUnderflow);
+        }
+        self.load(rsp, Width::Word)
     }
-    fn reg_mut(&mut self, reg: Register) -> &mut u16 {
-        &mut self.reg[reg as usize]
+    fn jump_if(&mut self, loc: Loc, cond: bool) -> Result<(), FaultKind> {
+        if cond {
+            let target = self.loc(loc, Width::Word)?;
+            self.check_target(target)?;
+            self.rip = target;
+        }
+        Ok(())
+    }
+    /// whether the given flag is currently set
+    pub fn flag(&self, flag: Flag) -> bool {
+        (self.flag & flag as u16) != 0
+    }
+    fn set_flag(&mut self, flag: Flag, set: bool) {
+        if set {
+            self.flag |= flag as u16;
+        } else {
+            self.flag &= !(flag as u16);
+        }
     }
-    fn set_reg(&mut self, reg: Register, val: u16) {
+    /// sets the flags that only depend on the result of an operation
+    fn set_result_flags(&mut self, res: u16, w: Width) {
+        self.set_flag(Flag::Sf, res & w.sign() != 0);
+        self.set_flag(Flag::Zf, res & w.mask() == 0);
+        self.set_flag(Flag::Pf, (res as u8).count_ones().is_multiple_of(2));
+    }
+    fn add(&mut self, a: u16, b: u16, w: Width) -> u16 {
+        let wide = (a & w.mask()) as u32 + (b & w.mask()) as u32;
+        let res = wide as u16 & w.mask();
+        self.set_flag(Flag::Cf, wide > w.mask() as u32);
+        self.set_flag(Flag::Of, (a ^ res) & (b ^ res) & w.sign() != 0);
+        self.set_flag(Flag::Af, (a ^ b ^ res) & 0x10 != 0);
+        self.set_result_flags(res, w);
+        res
+    }
+    fn sub(&mut self, a: u16, b: u16, w: Width) -> u16 {
+        let res = a.wrapping_sub(b) & w.mask();
+        self.set_flag(Flag::Cf, a & w.mask() < b & w.mask());
+        self.set_flag(Flag::Of, (a ^ b) & (a ^ res) & w.sign() != 0);
+        self.set_flag(Flag::Af, (a ^ b ^ res) & 0x10 != 0);
+        self.set_result_flags(res, w);
+        res
+    }
+    /// bitwise operations clear carry & overflow
+    fn logic(&mut self, res: u16, w: Width) -> u16 {
+        self.set_flag(Flag::Cf, false);
+        self.set_flag(Flag::Of, false);
+        self.set_flag(Flag::Af, false);
+        self.set_result_flags(res, w);
+        res & w.mask()
+    }
+    /// sets the flags of a shift, a count of zero leaves the flags untouched
+    fn shift_flags(&mut self, count: u32, res: u16, w: Width, carry: bool, overflow: bool) {
+        if count == 0 {
+            return;
+        }
+        self.set_flag(Flag::Cf, carry);
+        self.set_flag(Flag::Of, count == 1 && overflow);
+        self.set_flag(Flag::Af, false);
+        self.set_result_flags(res, w);
+    }
+    fn shl(&mut self, a: u16, count: u32, w: Width) -> u16 {
+        let wide = ((a & w.mask()) as u32) << count;
+        let res = wide as u16 & w.mask();
+        let carry = (wide >> w.bits()) & 1 != 0;
+        self.shift_flags(count, res, w, carry, (res & w.sign() != 0) != carry);
+        res
+    }
+    fn shr(&mut self, a: u16, count: u32, w: Width) -> u16 {
+        let a = (a & w.mask()) as u32;
+        let res = (a >> count) as u16;
+        let carry = count != 0 && (a >> (count - 1)) & 1 != 0;
+        self.shift_flags(count, res, w, carry, a as u16 & w.sign() != 0);
+        res
+    }
+    fn sar(&mut self, a: u16, count: u32, w: Width) -> u16 {
+        let signed = w.sign_extend(a);
+        let res = (signed >> count) as u16 & w.mask();
+        let carry = count != 0 && (signed >> (count - 1)) & 1 != 0;
+        self.shift_flags(count, res, w, carry, false);
+        res
+    }
+    /// rotates only change the carry & overflow flags
+    fn rol(&mut self, a: u16, count: u32, w: Width) -> u16 {
+        let res = w.rotate_left(a, count);
+        if count != 0 {
+            let carry = res & 1 != 0;
+            self.set_flag(Flag::Cf, carry);
+            self.set_flag(Flag::Of, count == 1 && (res & w.sign() != 0) != carry);
+        }
+        res
+    }
+    fn ror(&mut self, a: u16, count: u32, w: Width) -> u16 {
+        let res = w.rotate_right(a, count);
+        if count != 0 {
+            self.set_flag(Flag::Cf, res & w.sign() != 0);
+            self.set_flag(Flag::Of, count == 1 && ((res ^ res << 1) & w.sign() != 0));
+        }
+        res
+    }
+    /// arithmetic wraps around unless overflow is set to trap, in which case
+    /// `res` is never stored
+    fn trap_overflow(&self, res: u16) -> Result<u16, FaultKind> {
+        if self.options.trap_overflow && self.flag(Flag::Of) {
+            return Err(FaultKind::Overflow);
+        }
+        Ok(res)
+    }
+    /// runs `op` without letting it change the carry flag, as `inc` & `dec` do
+    fn keep_carry(&mut self, op: impl FnOnce(&mut Self) -> u16) -> u16 {
+        let carry = self.flag(Flag::Cf);
+        let res = op(self);
+        self.set_flag(Flag::Cf, carry);
+        res
+    }
+    /// the register pair used by multiplication & division, `ax` or `rdx:rax`
+    fn wide(&self, w: Width) -> u32 {
+        match w {
+            Width::Byte => self.reg(Register::RAX) as u32,
+            Width::Word => (self.reg(Register::RDX) as u32) << 16 | self.reg(Register::RAX) as u32,
+        }
+    }
+    fn set_wide(&mut self, w: Width, val: u32) {
+        if let Width::Word = w {
+            self.set_reg(Register::RDX, (val >> 16) as u16);
+        }
+        self.set_reg(Register::RAX, val as u16);
+    }
+    /// stores a division's result, in `al` & `ah` or `rax` & `rdx`
+    fn set_quotient(&mut self, w: Width, quotient: u16, remainder: u16) {
+        match w {
+            Width::Byte => self.set_reg(Register::RAX, (remainder & 0xff) << 8 | quotient & 0xff),
+            Width::Word => {
+                self.set_reg(Register::RAX, quotient);
+                self.set_reg(Register::RDX, remainder);
+            }
+        }
+    }
+    /// applies a binary `op` to a location & value, storing the result in the location
+    fn binary(
+        &mut self,
+        vl: LocThenVal,
+        op: impl FnOnce(&mut Self, u16, u16, Width) -> u16,
+    ) -> Result<(), FaultKind> {
+        self.try_binary(vl, |vm, a, b, w| Ok(op(vm, a, b, w)))
+    }
+    /// like [`BasmVM::binary`], but `op` may fault, leaving the location as it was
+    fn try_binary(
+        &mut self,
+        vl: LocThenVal,
+        op: impl FnOnce(&mut Self, u16, u16, Width) -> Result<u16, FaultKind>,
+    ) -> Result<(), FaultKind> {
+        let w = vl.width();
+        let LocThenVal(loc, val) = vl;
+        let b = self.value(val, w)?;
+        self.try_update(loc, w, |vm, a| op(vm, a, b, w))
+    }
+    /// applies `op` to the value stored at `loc`
+    fn update(
+        &mut self,
+        loc: Loc,
+        w: Width,
+        op: impl FnOnce(&mut Self, u16) -> u16,
+    ) -> Result<(), FaultKind> {
+        self.try_update(loc, w, |vm, a| Ok(op(vm, a)))
+    }
+    /// like [`BasmVM::update`], but `op` may fault, leaving the location as it was
+    fn try_update(
+        &mut self,
+        loc: Loc,
+        w: Width,
+        op: impl FnOnce(&mut Self, u16) -> Result<u16, FaultKind>,
+    ) -> Result<(), FaultKind> {
+        let old = self.dest(loc, w)?;
+        let new = op(self, old)?;
+        self.set_loc(loc, new, w)
+    }
+    pub fn reg(&self, reg: Register) -> u16 {
+        self.reg[reg as usize]
+    }
+    pub fn set_reg(&mut self, reg: Register, val: u16) {
         self.reg[reg as usize] = val;
     }
-    fn mem(&self, address: u16) -> u16 {
-        self.mem[address as usize]
+    pub fn reg_part(&self, reg: Register, part: RegPart) -> u16 {
+        let val = self.reg(reg);
+        match part {
+            RegPart::Full => val,
+            RegPart::Low => val & 0xff,
+            RegPart::High => val >> 8,
+        }
+    }
+    /// writes to a view of a register, leaving the rest of the register untouched
+    fn set_reg_part(&mut self, reg: Register, part: RegPart, val: u16) {
+        let old = self.reg(reg);
+        let new = match part {
+            RegPart::Full => val,
+            RegPart::Low => old & 0xff00 | val & 0xff,
+            RegPart::High => old & 0x00ff | (val & 0xff) << 8,
+        };
+        self.set_reg(reg, new);
     }
-    fn mem_mut(&mut self, address: u16) -> &mut u16 {
-        &mut self.mem[address as usize]
+    /// the bytes of memory in `address..address + len`
+    pub fn slice(&self, address: u16, len: u16) -> Result<&[u8], FaultKind> {
+        let at = address as usize;
+        self.mem
+            .get(at..at + len as usize)
+            .ok_or(FaultKind::MemoryOutOfRange(address))
     }
-    fn loc_mut(&mut self, loc: Loc) -> &mut u16 {
+    /// the bytes of a nul terminated string, without the nul
+    pub fn c_str(&self, address: u16) -> Result<&[u8], FaultKind> {
+        let rest = &self.mem[address as usize..];
+        let len = rest
+            .iter()
+            .position(|&b| b == 0)
+            .ok_or(FaultKind::MemoryOutOfRange(address))?;
+        Ok(&rest[..len])
+    }
+    pub fn slice_mut(&mut self, address: u16, len: u16) -> Result<&mut [u8], FaultKind> {
+        let at = address as usize;
+        self.mem
+            .get_mut(at..at + len as usize)
+            .ok_or(FaultKind::MemoryOutOfRange(address))
+    }
+    /// loads a little endian value of the given width from memory
+    pub fn load(&self, address: u16, w: Width) -> Result<u16, FaultKind> {
+        Ok(match *self.slice(address, w.bytes())? {
+            [b] => b as u16,
+            [a, b] => u16::from_le_bytes([a, b]),
+            _ => unreachable!(),
+        })
+    }
+    /// stores a little endian value of the given width into memory
+    pub fn store(&mut self, address: u16, val: u16, w: Width) -> Result<(), FaultKind> {
+        let bytes = val.to_le_bytes();
+        self.write_bytes(address, &bytes[..w.bytes() as usize])
+    }
+    /// copies bytes into memory, as seen by tracers
+    ///
+    /// writes made through [`BasmVM::slice_mut`] are not seen
+    pub fn write_bytes(&mut self, address: u16, bytes: &[u8]) -> Result<(), FaultKind> {
+        let dest = self.slice_mut(address, bytes.len() as u16)?;
+        let old = dest.into();
+        dest.copy_from_slice(bytes);
+        if let Some(journal) = &mut self.journal {
+            journal.push(MemWrite {
+                address,
+                old,
+                new: bytes.into(),
+            });
+        }
+        Ok(())
+    }
+    /// the value currently stored at a location
+    fn dest(&self, loc: Loc, w: Width) -> Result<u16, FaultKind> {
+        match loc.location {
+            LocKind::Mem(ad) => self.load(ad, w),
+            LocKind::Reg(reg, part) if loc.deref => self.load(self.reg_part(reg, part), w),
+            LocKind::Reg(reg, part) => Ok(self.reg_part(reg, part)),
+            LocKind::Sym(_) => Err(FaultKind::IllegalInstruction),
+        }
+    }
+    fn set_loc(&mut self, loc: Loc, val: u16, w: Width) -> Result<(), FaultKind> {
         match loc.location {
-            LocKind::Mem(ad) if loc.deref => self.mem_mut(ad),
-            LocKind::Mem(ad) => self.mem_mut(ad),
-            LocKind::Reg(reg) if loc.deref => self.mem_mut(self.reg(reg)),
-            LocKind::Reg(reg) => self.reg_mut(reg),
-            LocKind::Sym(_) => unreachable!(),
+            LocKind::Mem(ad) => self.store(ad, val, w),
+            LocKind::Reg(reg, part) if loc.deref => self.store(self.reg_part(reg, part), val, w),
+            LocKind::Reg(reg, part) => {
+                self.set_reg_part(reg, part, val);
+                Ok(())
+            }
+            LocKind::Sym(_) => Err(FaultKind::IllegalInstruction),
         }
     }
-    fn loc(&mut self, loc: Loc) -> u16 {
+    /// a location used as a value, where a bare memory location is its address
+    fn loc(&self, loc: Loc, w: Width) -> Result<u16, FaultKind> {
         match loc.location {
-            LocKind::Mem(ad) if loc.deref => self.mem(self.mem(ad)),
-            LocKind::Mem(ad) => ad,
-            LocKind::Reg(reg) if loc.deref => self.mem(self.reg(reg)),
-            LocKind::Reg(reg) => self.reg(reg),
-            LocKind::Sym(_) => unreachable!(),
+            LocKind::Mem(ad) if !loc.deref => Ok(ad),
+            _ => self.dest(loc, w),
         }
     }
-    fn value(&mut self, val: Value) -> u16 {
+    fn value(&mut self, val: Value, w: Width) -> Result<u16, FaultKind> {
         // println!("{val:#?}");
         match val {
-            Value::Loc(loc) => self.loc(loc),
-            Value::Word(v) => v,
-            Value::Words(w) => w.get(0).copied().unwrap_or_default(),
+            Value::Loc(loc) => self.loc(loc, w),
+            Value::Word(v) => Ok(v),
+            Value::Words(w) => Ok(w.first().copied().unwrap_or_default()),
         }
     }
 }
 
+/// shift counts are masked to 5 bits, as on x86
+fn shift_count(val: u16) -> u32 {
+    (val & 0x1f) as u32
+}
+
 pub const REGISTER_COUNT: usize = 16;
-pub const MEM_SIZE: usize = u16::MAX as usize;
+/// memory is addressed per byte, with every u16 being a valid address
+pub const MEM_SIZE: usize = u16::MAX as usize + 1;
+/// the number of bytes a single encoded instruction takes up
+pub const INS_SIZE: u16 = 8;
+/// the memory header holds the start & end of the code, then the entry point
+pub const CODE_START: u16 = 0;
+pub const CODE_END: u16 = 2;
+pub const ENTRY: u16 = 4;
+/// the initial stack pointer, the stack grows down from the end of memory
+pub const STACK_TOP: u16 = u16::MAX;
 
 // TODO: create run/rest counter part to call/ret
 // maybe also a proc instruction?
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Register {
     /// accumulator, volatile, return value
     RAX,
@@ -285,7 +1384,81 @@ pub enum Register {
     R15,
 }
 
-#[derive(Debug)]
+/// the part of a register being accessed
+///
+/// registers are 16 bits wide, so `ax`, `eax` & `rax` all name the full register
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegPart {
+    /// `rax`
+    Full,
+    /// the low byte, `al`
+    Low,
+    /// the high byte, `ah`
+    High,
+}
+
+impl RegPart {
+    pub fn width(self) -> Width {
+        match self {
+            RegPart::Full => Width::Word,
+            RegPart::Low | RegPart::High => Width::Byte,
+        }
+    }
+}
+
+/// the size of an operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Width {
+    Byte,
+    Word,
+}
+
+impl Width {
+    pub fn bits(self) -> u32 {
+        match self {
+            Width::Byte => 8,
+            Width::Word => 16,
+        }
+    }
+    pub fn mask(self) -> u16 {
+        match self {
+            Width::Byte => 0xff,
+            Width::Word => 0xffff,
+        }
+    }
+    pub fn bytes(self) -> u16 {
+        match self {
+            Width::Byte => 1,
+            Width::Word => 2,
+        }
+    }
+    pub fn sign(self) -> u16 {
+        match self {
+            Width::Byte => 0x80,
+            Width::Word => 0x8000,
+        }
+    }
+    pub fn sign_extend(self, val: u16) -> i32 {
+        match self {
+            Width::Byte => val as u8 as i8 as i32,
+            Width::Word => val as i16 as i32,
+        }
+    }
+    fn rotate_left(self, val: u16, count: u32) -> u16 {
+        match self {
+            Width::Byte => (val as u8).rotate_left(count) as u16,
+            Width::Word => val.rotate_left(count),
+        }
+    }
+    fn rotate_right(self, val: u16, count: u32) -> u16 {
+        match self {
+            Width::Byte => (val as u8).rotate_right(count) as u16,
+            Width::Word => val.rotate_right(count),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Flag {
     /// sign
     Sf = 0b1,
@@ -301,6 +1474,63 @@ pub enum Flag {
     Of = 0b100000,
 }
 
+impl Flag {
+    pub const ALL: [Flag; 6] = [Flag::Sf, Flag::Zf, Flag::Cf, Flag::Af, Flag::Pf, Flag::Of];
+}
+
+impl Register {
+    /// the name of a view of the register, as written in source
+    pub fn name(self, part: RegPart) -> String {
+        use Register::*;
+        let full = self.to_string();
+        match (part, self) {
+            (RegPart::Full, _) => full,
+            (RegPart::Low, RAX | RBX | RCX | RDX) => format!("{}l", &full[1..2]),
+            (RegPart::High, RAX | RBX | RCX | RDX) => format!("{}h", &full[1..2]),
+            (RegPart::Low, RSI | RDI | RSP | RBP) => format!("{}l", &full[1..]),
+            (RegPart::Low, _) => format!("{full}b"),
+            (RegPart::High, _) => format!("{full}h"),
+        }
+    }
+}
+
+impl std::fmt::Display for Register {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        use Register::*;
+        let name = match self {
+            RAX => "rax",
+            RBX => "rbx",
+            RCX => "rcx",
+            RDX => "rdx",
+            RSI => "rsi",
+            RDI => "rdi",
+            RSP => "rsp",
+            RBP => "rbp",
+            R08 => "r08",
+            R09 => "r09",
+            R10 => "r10",
+            R11 => "r11",
+            R12 => "r12",
+            R13 => "r13",
+            R14 => "r14",
+            R15 => "r15",
+        };
+        f.write_str(name)
+    }
+}
+
+impl std::convert::TryFrom<u16> for RegPart {
+    type Error = ();
+    fn try_from(v: u16) -> Result<Self, Self::Error> {
+        match v {
+            0 => Ok(RegPart::Full),
+            1 => Ok(RegPart::Low),
+            2 => Ok(RegPart::High),
+            _ => Err(()),
+        }
+    }
+}
+
 impl std::convert::TryFrom<u16> for Register {
     type Error = ();
     fn try_from(v: u16) -> Result<Self, Self::Error> {
@@ -1,40 +1,134 @@
-use std::process::ExitCode;
+use std::{env::args, path::Path, process::ExitCode};
+
+use basm_vm::replay::{Replay, ReplayLog};
+use basm_vm::trace::{JsonTracer, TextTracer};
+use basm_vm::{BasmVM, Termination};
 
 fn main() -> ExitCode {
-    let src = read_in().expect("failed to read stdin");
-    match basm_vm::BasmVM::parse(&src) {
+    let resume = flag_value("--resume");
+    let fs_root = flag_value("--fs-root");
+    let vm = match &resume {
+        Some(path) => match BasmVM::load_snapshot(path, fs_root.as_deref().map(Path::new)) {
+            Ok(vm) => Ok(vm),
+            Err(err) => {
+                eprintln!("unable to resume from {path}: {err}");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => {
+            // with a program file, stdin is left for the program to read
+            let src = match program_path() {
+                Some(path) => std::fs::read_to_string(path).expect("failed to read program"),
+                None => read_in().expect("failed to read stdin"),
+            };
+            BasmVM::parse(&src)
+        }
+    };
+    match vm {
         Ok(mut vm) => {
+            // a snapshot keeps its options, unless a flag overrides them
+            vm.options.trap_overflow |= args().any(|s| s == "--trap-overflow");
+            // a resumed vm has its files restored within the root already
+            if let (Some(root), None) = (&fs_root, &resume) {
+                vm.fs = basm_vm::fs::Vfs::host(root);
+            }
+            // traces go to stderr, leaving stdout to the program
+            if args().any(|s| s == "--trace") {
+                vm.tracer = Some(Box::new(TextTracer(std::io::stderr())));
+            } else if args().any(|s| s == "--trace=json") {
+                vm.tracer = Some(Box::new(JsonTracer(std::io::stderr())));
+            }
+            let record = flag_value("--record");
+            if record.is_some() {
+                vm.replay = Some(Replay::record());
+            } else if let Some(path) = flag_value("--replay") {
+                match ReplayLog::load(&path) {
+                    Ok(log) => vm.replay = Some(Replay::play(log)),
+                    Err(err) => {
+                        eprintln!("unable to replay {path}: {err}");
+                        return ExitCode::FAILURE;
+                    }
+                }
+            }
+            let coverage = flag_value("--coverage");
+            if coverage.is_some() {
+                vm.coverage = Some(Default::default());
+            }
+            let profile = args().any(|s| s == "--profile");
+            let collapsed = args().any(|s| s == "--profile=collapsed");
+            if profile || collapsed {
+                vm.profiler = Some(Default::default());
+            }
+            if let Some(n) = flag_value("--max-instructions").and_then(|n| n.parse().ok()) {
+                vm.options.max_instructions = Some(n);
+            }
+            if let Some(ms) = flag_value("--time-limit-ms").and_then(|ms| ms.parse().ok()) {
+                vm.options.time_limit = Some(std::time::Duration::from_millis(ms));
+            }
             println!("running:");
             // println!("{:#?}", vm.reg);
-            let ec = vm.run();
-            // println!("{:#?}", vm.reg);
-            ec
-        }
-        Err(errs) => {
-            let mut o = "".to_owned();
-            match errs {
-                basm_vm::VmError::ParseError(errs) => {
-                    for err in errs {
-                        o.push_str(&err.to_string());
-                    }
+            let outcome = match resume {
+                Some(_) => vm.resume(),
+                None => vm.run(),
+            };
+            match &outcome.reason {
+                Termination::Faulted(err) => {
+                    eprintln!("{err}");
+                    eprint!("{}", vm.symbols.backtrace(&vm.backtrace()));
                 }
-                basm_vm::VmError::ReparseError(errs) => {
-                    for err in errs {
-                        o.push_str(&format!("\n{err:?}"));
-                    }
+                Termination::BudgetExhausted(_) => {
+                    eprintln!("{} at {:#06x}", outcome.reason, outcome.registers.rip)
                 }
-                basm_vm::VmError::EncodeError(errs) => {
-                    for err in errs {
-                        o.push_str(&format!("\n{err:?}"));
-                    }
+                Termination::Exited | Termination::End => (),
+            }
+            if let (Some(path), Some(replay)) = (record, &vm.replay) {
+                if let Err(e) = replay.log().save(&path) {
+                    eprintln!("failed to write the recording to {path}: {e}");
+                }
+            }
+            if let (Some(path), Some(covered)) = (coverage, &vm.coverage) {
+                let source = program_path().unwrap_or_else(|| "stdin".to_string());
+                let lcov = covered.lcov(&vm, &source);
+                if let Err(e) = std::fs::write(&path, lcov) {
+                    eprintln!("failed to write coverage to {path}: {e}");
+                }
+            }
+            if let Some(profiler) = &vm.profiler {
+                match collapsed {
+                    true => eprint!("{}", profiler.collapsed(&vm.symbols)),
+                    false => eprint!("{}", profiler.report(&vm.symbols)),
                 }
             }
-            println!("unable to run:{o}");
+            outcome.into()
+        }
+        Err(errs) => {
+            println!("unable to run:{errs}");
             ExitCode::FAILURE
         }
     }
 }
 
+/// the first argument which is not a flag
+fn program_path() -> Option<String> {
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--fs-root" | "--coverage" | "--resume" | "--record" | "--replay"
+            | "--max-instructions" | "--time-limit-ms" => {
+                args.next();
+            }
+            flag if flag.starts_with("--") => (),
+            _ => return Some(arg),
+        }
+    }
+    None
+}
+
+/// the argument following a flag
+fn flag_value(flag: &str) -> Option<String> {
+    args().skip_while(|s| s != flag).nth(1)
+}
+
 fn read_in() -> std::io::Result<String> {
     use std::io::{stdin, Read};
     let mut out = String::new();
@@ -0,0 +1,102 @@
+use std::fmt::Write;
+
+use ahash::AHashMap;
+
+use crate::backtrace::Frame;
+use crate::symbols::Symbols;
+
+/// how many of the hottest instructions a report lists
+const HOTTEST: usize = 10;
+
+/// counts the instructions a program executes, by address & by call stack
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    hits: AHashMap<u16, u64>,
+    /// the instructions executed under each call stack, keyed by the entry of
+    /// each function on it, outermost first
+    stacks: AHashMap<Vec<u16>, u64>,
+    /// where the program was entered, at the bottom of every stack
+    entry: u16,
+    /// the stack being counted, kept to save allocating one per instruction
+    stack: Vec<u16>,
+    total: u64,
+}
+
+impl Profiler {
+    /// forgets everything counted, starting again at `entry`
+    pub(crate) fn start(&mut self, entry: u16) {
+        *self = Self::default();
+        self.entry = entry;
+    }
+    /// counts an instruction about to run within `calls`
+    pub(crate) fn record(&mut self, address: u16, calls: &[Frame]) {
+        self.total += 1;
+        *self.hits.entry(address).or_default() += 1;
+        self.stack.clear();
+        self.stack.push(self.entry);
+        self.stack.extend(calls.iter().map(|frame| frame.target));
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(n) => *n += 1,
+            None => {
+                self.stacks.insert(self.stack.clone(), 1);
+            }
+        }
+    }
+    /// the number of instructions counted
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+    /// how many times the instruction at `address` ran
+    pub fn hits(&self, address: u16) -> u64 {
+        self.hits.get(&address).copied().unwrap_or_default()
+    }
+    /// the instructions counted under each label, most first
+    pub fn by_label(&self, symbols: &Symbols) -> Vec<(String, u64)> {
+        let mut labels = AHashMap::<String, u64>::new();
+        for (&address, &n) in &self.hits {
+            *labels.entry(label(symbols, address)).or_default() += n;
+        }
+        let mut labels: Vec<_> = labels.into_iter().collect();
+        labels.sort_by(|(a, n), (b, m)| m.cmp(n).then_with(|| a.cmp(b)));
+        labels
+    }
+    /// a ranked report of the labels, then the hottest instructions
+    pub fn report(&self, symbols: &Symbols) -> String {
+        let percent = |n: u64| n as f64 * 100.0 / self.total.max(1) as f64;
+        let mut out = format!("{} instructions executed\n\n", self.total);
+        let _ = writeln!(out, "{:>10} {:>6}  label", "count", "%");
+        for (label, n) in self.by_label(symbols) {
+            let _ = writeln!(out, "{n:>10} {:>5.1}%  {label}", percent(n));
+        }
+        let mut hits: Vec<_> = self.hits.iter().map(|(&a, &n)| (a, n)).collect();
+        hits.sort_by(|(a, n), (b, m)| m.cmp(n).then_with(|| a.cmp(b)));
+        let _ = writeln!(out, "\n{:>10} {:>6}  instruction", "count", "%");
+        for (address, n) in hits.into_iter().take(HOTTEST) {
+            let at = symbols.describe(address);
+            let _ = writeln!(out, "{n:>10} {:>5.1}%  {address:#06x} {at}", percent(n));
+        }
+        out
+    }
+    /// the call stacks in the collapsed format flamegraph tools read,
+    /// e.g. `_start;print 12`
+    pub fn collapsed(&self, symbols: &Symbols) -> String {
+        let mut lines: Vec<_> = self
+            .stacks
+            .iter()
+            .map(|(stack, n)| {
+                let frames: Vec<_> = stack.iter().map(|&a| label(symbols, a)).collect();
+                format!("{} {n}\n", frames.join(";"))
+            })
+            .collect();
+        lines.sort();
+        lines.concat()
+    }
+}
+
+/// the label an address falls under, or the address itself
+fn label(symbols: &Symbols, address: u16) -> String {
+    match symbols.enclosing_label(address) {
+        Some((label, _)) => label.to_string(),
+        None => format!("{address:#06x}"),
+    }
+}
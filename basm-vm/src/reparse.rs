@@ -1,4 +1,4 @@
-use std::{str::FromStr, u16};
+use std::str::FromStr;
 
 use basm::{
     parse::{ParseError, Parser},
@@ -7,7 +7,8 @@ use basm::{
 use string_interner::{DefaultBackend, DefaultSymbol, StringInterner};
 
 use crate::{
-    Code, GlobalMap, LabelMap, Loc, LocKind, LocThenVal, Register, Sequence, Value, VariableMap,
+    Code, GlobalMap, LabelMap, Loc, LocKind, LocThenVal, RegPart, Register, Sequence, Value,
+    VariableMap,
 };
 
 #[cfg(test)]
@@ -38,6 +39,7 @@ struct Reparser {
     si: StringInterner<DefaultBackend>,
     lines: Vec<Line>,
     sequences: Vec<Sequence>,
+    seq_lines: Vec<u32>,
     variables: VariableMap,
     globals: GlobalMap,
     labels: LabelMap,
@@ -47,14 +49,12 @@ impl Reparser {
     // TODO: compile errors instead of fail fast.
     fn reparse(mut self) -> (Code, Vec<ReparseError>) {
         let errors: Vec<_> = (0..self.lines.len())
-            .filter_map(|i| match self.reparse_line(i) {
-                Ok(()) => None,
-                Err(e) => Some(e),
-            })
+            .filter_map(|i| self.reparse_line(i).err())
             .collect();
 
         let code = Code {
             sequences: self.sequences,
+            lines: self.seq_lines,
             si: self.si,
             variables: self.variables,
             globals: self.globals,
@@ -63,10 +63,10 @@ impl Reparser {
         (code, errors)
     }
 
-    fn reparse_line(&mut self, line: usize) -> Result<(), ReparseError> {
+    fn reparse_line(&mut self, index: usize) -> Result<(), ReparseError> {
         use basm::Line::*;
-        let line = &self.lines[line];
-        Ok(match line {
+        let line = &self.lines[index];
+        match line {
             NoOp => (),
             Global { name } => {
                 self.globals.insert(*name);
@@ -78,29 +78,31 @@ impl Reparser {
                 self.labels.insert(*name, self.sequences.len() as u16);
             }
             Instruction { ins, values } => {
-                let seq = Sequence::reparse(&self, ins, values)?;
+                let seq = Sequence::reparse(self, ins, values)?;
                 self.sequences.push(seq);
+                self.seq_lines.push(index as u32);
             }
             Variable {
                 name,
                 r#type,
                 values,
             } => {
-                let value = self.handle_var(*r#type, &values)?;
+                let value = self.handle_var(*r#type, values)?;
                 self.variables.insert(*name, value);
             }
-        })
+        }
+        Ok(())
     }
 
     fn handle_var(
         &self,
         r#type: DefaultSymbol,
         values: &[PValue],
-    ) -> Result<Box<[u16]>, ReparseError> {
+    ) -> Result<Box<[u8]>, ReparseError> {
         match self.resolve(r#type)? {
-            "str" => self.parse_str_value(&values),
+            "str" => self.parse_str_value(values),
             "bss" => {
-                let [PValue::Digit(_, n)] = &values[..] else {
+                let [PValue::Digit(_, n)] = values else {
                     return Err(ReparseError::InputError(InputError::InvalidType(r#type)));
                 };
                 Ok(vec![0; *n as usize].into_boxed_slice())
@@ -109,13 +111,14 @@ impl Reparser {
         }
     }
 
-    fn parse_str_value(&self, values: &[PValue]) -> Result<Box<[u16]>, ReparseError> {
+    /// strings are stored byte by byte, as are digits
+    fn parse_str_value(&self, values: &[PValue]) -> Result<Box<[u8]>, ReparseError> {
         let mut vars = Vec::new();
         for value in values {
             match value {
-                PValue::Digit(_, n) => vars.push(*n),
+                PValue::Digit(_, n) => vars.push(*n as u8),
                 PValue::Deref(symbol) | PValue::Ident(symbol) | PValue::String(symbol) => {
-                    var_read_string(&mut vars, self.resolve(*symbol)?);
+                    vars.extend_from_slice(self.resolve(*symbol)?.as_bytes());
                 }
             }
         }
@@ -168,13 +171,11 @@ impl Reparser {
     fn reparse_value(&self, value: &PValue) -> Result<Value, ReparseError> {
         Ok(match value {
             PValue::Deref(sym) => Value::Loc(Loc {
-                location: Register::from_str(self.resolve(*sym)?)
-                    .map_or(LocKind::Sym(*sym), LocKind::Reg),
+                location: self.location(*sym)?,
                 deref: true,
             }),
             PValue::Ident(sym) => Value::Loc(Loc {
-                location: Register::from_str(self.resolve(*sym)?)
-                    .map_or(LocKind::Sym(*sym), LocKind::Reg),
+                location: self.location(*sym)?,
                 deref: false,
             }),
             PValue::String(sym) => {
@@ -186,10 +187,17 @@ impl Reparser {
         })
     }
 
+    fn location(&self, sym: DefaultSymbol) -> Result<LocKind, ReparseError> {
+        Ok(register(self.resolve(sym)?)
+            .map_or(LocKind::Sym(sym), |(reg, part)| LocKind::Reg(reg, part)))
+    }
+
     fn resolve(&self, symbol: DefaultSymbol) -> Result<&str, ReparseError> {
         self.si
             .resolve(symbol)
-            .ok_or_else(|| ReparseError::CompileError(CompileError::InvalidSymbol(symbol)))
+            .ok_or(ReparseError::CompileError(CompileError::InvalidSymbol(
+                symbol,
+            )))
     }
 }
 
@@ -208,15 +216,35 @@ impl Sequence {
             "xor" => Xor(dec.loc_then_value(values)?),
             "and" => And(dec.loc_then_value(values)?),
             "or" => Or(dec.loc_then_value(values)?),
+            "shl" | "sal" => Shl(dec.loc_then_value(values)?),
+            "shr" => Shr(dec.loc_then_value(values)?),
+            "sar" => Sar(dec.loc_then_value(values)?),
+            "rol" => Rol(dec.loc_then_value(values)?),
+            "ror" => Ror(dec.loc_then_value(values)?),
             // any value
             "push" => Push(dec.single_value(values)?.clone()),
+            "mul" => Mul(dec.single_value(values)?),
+            "imul" => Imul(dec.single_value(values)?),
+            "div" => Div(dec.single_value(values)?),
+            "idiv" => Idiv(dec.single_value(values)?),
             // loc
             "pop" => Pop(dec.loc(values)?),
             "call" => Call(dec.loc(values)?),
-            "je" => Je(dec.loc(values)?),
-            "jne" => Jne(dec.loc(values)?),
+            "jmp" => Jmp(dec.loc(values)?),
+            "je" | "jz" => Je(dec.loc(values)?),
+            "jne" | "jnz" => Jne(dec.loc(values)?),
+            "jl" => Jl(dec.loc(values)?),
+            "jle" => Jle(dec.loc(values)?),
+            "jg" => Jg(dec.loc(values)?),
+            "jge" => Jge(dec.loc(values)?),
+            "jb" => Jb(dec.loc(values)?),
+            "jbe" => Jbe(dec.loc(values)?),
+            "ja" => Ja(dec.loc(values)?),
+            "jae" => Jae(dec.loc(values)?),
             "inc" => Inc(dec.loc(values)?),
             "dec" => Dec(dec.loc(values)?),
+            "neg" => Neg(dec.loc(values)?),
+            "not" => Not(dec.loc(values)?),
             "cmp" => {
                 let (a, b) = dec.double_value(values)?;
                 Cmp(a.clone(), b.clone())
@@ -240,7 +268,7 @@ impl Sequence {
 }
 
 fn empty(values: &[PValue]) -> Result<(), ReparseError> {
-    if let [] = values {
+    if values.is_empty() {
         Ok(())
     } else {
         Err(ReparseError::InputError(InputError::InvalidArgCount {
@@ -277,22 +305,71 @@ impl FromStr for Register {
     }
 }
 
+/// parses a register name, including the names of its parts
+pub fn register(s: &str) -> Option<(Register, RegPart)> {
+    use RegPart::*;
+    use Register::*;
+    if let Ok(reg) = Register::from_str(s) {
+        return Some((reg, Full));
+    }
+    Some(match s {
+        "al" => (RAX, Low),
+        "ah" => (RAX, High),
+        "ax" | "eax" => (RAX, Full),
+        "bl" => (RBX, Low),
+        "bh" => (RBX, High),
+        "bx" | "ebx" => (RBX, Full),
+        "cl" => (RCX, Low),
+        "ch" => (RCX, High),
+        "cx" | "ecx" => (RCX, Full),
+        "dl" => (RDX, Low),
+        "dh" => (RDX, High),
+        "dx" | "edx" => (RDX, Full),
+        "sil" => (RSI, Low),
+        "si" | "esi" => (RSI, Full),
+        "dil" => (RDI, Low),
+        "di" | "edi" => (RDI, Full),
+        "spl" => (RSP, Low),
+        "sp" | "esp" => (RSP, Full),
+        "bpl" => (RBP, Low),
+        "bp" | "ebp" => (RBP, Full),
+        "r08b" => (R08, Low),
+        "r08w" | "r08d" => (R08, Full),
+        "r09b" => (R09, Low),
+        "r09w" | "r09d" => (R09, Full),
+        "r10b" => (R10, Low),
+        "r10w" | "r10d" => (R10, Full),
+        "r11b" => (R11, Low),
+        "r11w" | "r11d" => (R11, Full),
+        "r12b" => (R12, Low),
+        "r12w" | "r12d" => (R12, Full),
+        "r13b" => (R13, Low),
+        "r13w" | "r13d" => (R13, Full),
+        "r14b" => (R14, Low),
+        "r14w" | "r14d" => (R14, Full),
+        "r15b" => (R15, Low),
+        "r15w" | "r15d" => (R15, Full),
+        _ => return None,
+    })
+}
+
 // TODO: encode first word as length of str
 
+/// packs a string into little endian words, as a string immediate is in nasm
 fn var_read_string(vars: &mut Vec<u16>, s: &str) {
     let mut ce = s.as_bytes().chunks_exact(2);
-    while let Some(&[b, a]) = ce.next() {
+    while let Some(&[a, b]) = ce.next() {
         vars.push(u16::from_le_bytes([a, b]));
     }
-    if let [b] = ce.remainder() {
-        vars.push(u16::from_le_bytes([0, *b]));
+    if let [a] = ce.remainder() {
+        vars.push(u16::from_le_bytes([*a, 0]));
     }
 }
 
 pub fn reparse(src: &str) -> (Code, Either<Vec<ParseError>, Vec<ReparseError>>) {
-    let (Basm { si, lines }, errors) = Parser::base(&src).parse();
+    let (Basm { si, lines }, errors) = Parser::base(src).parse();
 
-    if errors.len() != 0 {
+    if !errors.is_empty() {
         return (Code::default(), Either::A(errors));
     }
 
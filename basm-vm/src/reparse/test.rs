@@ -18,6 +18,7 @@ fn check(src: &str, expect: Expect) {
         crate::Code {
             si: _,
             sequences,
+            lines: _,
             variables,
             globals,
             labels,
@@ -92,15 +93,15 @@ fn hello_world() {
             globals:
             SymbolU32 { value: 4 }
             variables:
-            (SymbolU32 { value: 3 }, [18533, 27756, 28460, 8279, 28530, 27748, 10])
+            (SymbolU32 { value: 3 }, [72, 101, 108, 108, 111, 44, 32, 87, 111, 114, 108, 100, 10])
             sequences:
-            Mov(ValueAndLoc { value: Digit(Decimal, 1), loc: Loc { deref: false, loc: SymbolU32 { value: 5 } } })
-            Mov(ValueAndLoc { value: Digit(Decimal, 1), loc: Loc { deref: false, loc: SymbolU32 { value: 7 } } })
-            Mov(ValueAndLoc { value: Ident(SymbolU32 { value: 3 }), loc: Loc { deref: false, loc: SymbolU32 { value: 8 } } })
-            Mov(ValueAndLoc { value: Digit(Decimal, 13), loc: Loc { deref: false, loc: SymbolU32 { value: 9 } } })
+            Mov(LocThenVal(Loc { location: Reg(RAX, Full), deref: false }, Word(1)))
+            Mov(LocThenVal(Loc { location: Reg(RDI, Full), deref: false }, Word(1)))
+            Mov(LocThenVal(Loc { location: Reg(RSI, Full), deref: false }, Loc(Loc { location: Sym(SymbolU32 { value: 3 }), deref: false })))
+            Mov(LocThenVal(Loc { location: Reg(RDX, Full), deref: false }, Word(13)))
             SysCall
-            Mov(ValueAndLoc { value: Digit(Decimal, 60), loc: Loc { deref: false, loc: SymbolU32 { value: 5 } } })
-            Xor(ValueAndLoc { value: Ident(SymbolU32 { value: 7 }), loc: Loc { deref: false, loc: SymbolU32 { value: 7 } } })
+            Mov(LocThenVal(Loc { location: Reg(RAX, Full), deref: false }, Word(60)))
+            Xor(LocThenVal(Loc { location: Reg(RDI, Full), deref: false }, Loc(Loc { location: Reg(RDI, Full), deref: false })))
             SysCall"#]],
     );
 }
@@ -119,30 +120,30 @@ fn print_any() {
             globals:
             SymbolU32 { value: 8 }
             variables:
-            (SymbolU32 { value: 3 }, [18533, 27756, 28460, 8279, 28530, 27748, 8448, 10, 0])
-            (SymbolU32 { value: 5 }, [22376, 24948, 10099, 8309, 28672, 10, 0])
-            (SymbolU32 { value: 7 }, [29800, 26995, 8297, 29472, 24864, 27759, 28263, 25970, 8300, 26990, 25888, 28518, 8308, 25976, 29742, 10, 0])
+            (SymbolU32 { value: 3 }, [72, 101, 108, 108, 111, 44, 32, 87, 111, 114, 108, 100, 33, 10, 0])
+            (SymbolU32 { value: 5 }, [87, 104, 97, 116, 39, 115, 32, 117, 112, 10, 0])
+            (SymbolU32 { value: 7 }, [116, 104, 105, 115, 32, 105, 115, 32, 97, 32, 108, 111, 110, 103, 101, 114, 32, 108, 105, 110, 101, 32, 111, 102, 32, 116, 101, 120, 116, 46, 10, 0])
             sequences:
-            Mov(ValueAndLoc { value: Ident(SymbolU32 { value: 3 }), loc: Loc { deref: false, loc: SymbolU32 { value: 9 } } })
-            Call(Loc { deref: false, loc: SymbolU32 { value: 11 } })
-            Mov(ValueAndLoc { value: Ident(SymbolU32 { value: 5 }), loc: Loc { deref: false, loc: SymbolU32 { value: 9 } } })
-            Call(Loc { deref: false, loc: SymbolU32 { value: 11 } })
-            Mov(ValueAndLoc { value: Ident(SymbolU32 { value: 7 }), loc: Loc { deref: false, loc: SymbolU32 { value: 9 } } })
-            Call(Loc { deref: false, loc: SymbolU32 { value: 11 } })
-            Mov(ValueAndLoc { value: Digit(Decimal, 60), loc: Loc { deref: false, loc: SymbolU32 { value: 9 } } })
-            Mov(ValueAndLoc { value: Digit(Decimal, 0), loc: Loc { deref: false, loc: SymbolU32 { value: 13 } } })
+            Mov(LocThenVal(Loc { location: Reg(RAX, Full), deref: false }, Loc(Loc { location: Sym(SymbolU32 { value: 3 }), deref: false })))
+            Call(Loc { location: Sym(SymbolU32 { value: 11 }), deref: false })
+            Mov(LocThenVal(Loc { location: Reg(RAX, Full), deref: false }, Loc(Loc { location: Sym(SymbolU32 { value: 5 }), deref: false })))
+            Call(Loc { location: Sym(SymbolU32 { value: 11 }), deref: false })
+            Mov(LocThenVal(Loc { location: Reg(RAX, Full), deref: false }, Loc(Loc { location: Sym(SymbolU32 { value: 7 }), deref: false })))
+            Call(Loc { location: Sym(SymbolU32 { value: 11 }), deref: false })
+            Mov(LocThenVal(Loc { location: Reg(RAX, Full), deref: false }, Word(60)))
+            Mov(LocThenVal(Loc { location: Reg(RDI, Full), deref: false }, Word(0)))
             SysCall
-            Push(Ident(SymbolU32 { value: 9 }))
-            Mov(ValueAndLoc { value: Digit(Decimal, 0), loc: Loc { deref: false, loc: SymbolU32 { value: 16 } } })
-            Inc(Loc { deref: false, loc: SymbolU32 { value: 9 } })
-            Inc(Loc { deref: false, loc: SymbolU32 { value: 16 } })
-            Mov(ValueAndLoc { value: Deref(SymbolU32 { value: 9 }), loc: Loc { deref: false, loc: SymbolU32 { value: 19 } } })
-            Cmp(Ident(SymbolU32 { value: 19 }), Digit(Decimal, 0))
-            Jne(Loc { deref: false, loc: SymbolU32 { value: 17 } })
-            Mov(ValueAndLoc { value: Digit(Decimal, 1), loc: Loc { deref: false, loc: SymbolU32 { value: 9 } } })
-            Mov(ValueAndLoc { value: Digit(Decimal, 1), loc: Loc { deref: false, loc: SymbolU32 { value: 13 } } })
-            Pop(Loc { deref: false, loc: SymbolU32 { value: 22 } })
-            Mov(ValueAndLoc { value: Ident(SymbolU32 { value: 16 }), loc: Loc { deref: false, loc: SymbolU32 { value: 24 } } })
+            Push(Loc(Loc { location: Reg(RAX, Full), deref: false }))
+            Mov(LocThenVal(Loc { location: Reg(RBX, Full), deref: false }, Word(0)))
+            Inc(Loc { location: Reg(RAX, Full), deref: false })
+            Inc(Loc { location: Reg(RBX, Full), deref: false })
+            Mov(LocThenVal(Loc { location: Reg(RCX, Low), deref: false }, Loc(Loc { location: Reg(RAX, Full), deref: true })))
+            Cmp(Loc(Loc { location: Reg(RCX, Low), deref: false }), Word(0))
+            Jne(Loc { location: Sym(SymbolU32 { value: 17 }), deref: false })
+            Mov(LocThenVal(Loc { location: Reg(RAX, Full), deref: false }, Word(1)))
+            Mov(LocThenVal(Loc { location: Reg(RDI, Full), deref: false }, Word(1)))
+            Pop(Loc { location: Reg(RSI, Full), deref: false })
+            Mov(LocThenVal(Loc { location: Reg(RDX, Full), deref: false }, Loc(Loc { location: Reg(RBX, Full), deref: false })))
             SysCall
             Ret"#]],
     );
@@ -0,0 +1,287 @@
+use std::path::Path;
+
+use serde_json::{json, Value};
+
+use crate::snapshot::{hex, unhex};
+use crate::syscall::{SyscallResult, Syscalls, SYS_WRITE};
+use crate::{BasmVM, FaultKind, Register};
+
+/// names the kind of document a replay log is
+pub const FORMAT: &str = "basm-vm replay";
+/// the newest replay log version this vm reads & the one it writes
+pub const VERSION: u64 = 1;
+
+/// records the result of every syscall, or feeds recorded results back
+#[derive(Debug, Clone)]
+pub enum Replay {
+    Record(ReplayLog),
+    /// makes no syscalls, taking their results from the log instead
+    Play {
+        log: ReplayLog,
+        next: usize,
+    },
+}
+
+/// the syscalls of a run, in the order they were made
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ReplayLog {
+    pub syscalls: Vec<SyscallRecord>,
+}
+
+/// what a syscall returned & what it stored into memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyscallRecord {
+    pub number: u16,
+    pub rax: u16,
+    pub result: Recorded,
+    pub writes: Vec<(u16, Box<[u8]>)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Recorded {
+    Continue,
+    Exit(u8),
+    /// the syscall faulted, which replaying repeats by making it for real
+    Fault,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// the document is json, but not a replay log
+    NotAReplay,
+    /// the log has a version this vm does not know
+    Version(u64),
+    /// the syscall at this index is malformed
+    Malformed(usize),
+}
+
+impl std::fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Json(err) => write!(f, "invalid json: {err}"),
+            Self::NotAReplay => write!(f, "not a {FORMAT}"),
+            Self::Version(version) => {
+                write!(
+                    f,
+                    "unsupported replay version {version}, expected {VERSION}"
+                )
+            }
+            Self::Malformed(i) => write!(f, "syscall {i} of the log is malformed"),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<std::io::Error> for ReplayError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for ReplayError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl Replay {
+    /// a recording with an empty log
+    pub fn record() -> Self {
+        Self::Record(ReplayLog::default())
+    }
+    pub fn play(log: ReplayLog) -> Self {
+        Self::Play { log, next: 0 }
+    }
+    pub fn log(&self) -> &ReplayLog {
+        match self {
+            Self::Record(log) | Self::Play { log, .. } => log,
+        }
+    }
+    /// starts the recording or the playback over
+    pub(crate) fn restart(&mut self) {
+        self.rewind(0);
+    }
+    /// how many syscalls have been recorded or played back
+    pub(crate) fn position(&self) -> usize {
+        match self {
+            Self::Record(log) => log.syscalls.len(),
+            Self::Play { next, .. } => *next,
+        }
+    }
+    /// forgets the syscalls after `position`, so they are recorded or played again
+    pub(crate) fn rewind(&mut self, position: usize) {
+        match self {
+            Self::Record(log) => log.syscalls.truncate(position),
+            Self::Play { next, .. } => *next = position,
+        }
+    }
+}
+
+impl ReplayLog {
+    pub fn to_json(&self) -> Value {
+        let syscalls: Vec<_> = self
+            .syscalls
+            .iter()
+            .map(|record| {
+                let writes: Vec<_> = record
+                    .writes
+                    .iter()
+                    .map(|(address, bytes)| json!({ "address": address, "bytes": hex(bytes) }))
+                    .collect();
+                let mut syscall = json!({
+                    "number": record.number,
+                    "rax": record.rax,
+                    "result": "continue",
+                    "writes": writes,
+                });
+                match record.result {
+                    Recorded::Continue => (),
+                    Recorded::Exit(code) => {
+                        syscall["result"] = json!("exit");
+                        syscall["code"] = json!(code);
+                    }
+                    Recorded::Fault => syscall["result"] = json!("fault"),
+                }
+                syscall
+            })
+            .collect();
+        json!({ "format": FORMAT, "version": VERSION, "syscalls": syscalls })
+    }
+    pub fn from_json(value: &Value) -> Result<Self, ReplayError> {
+        if value.get("format").and_then(Value::as_str) != Some(FORMAT) {
+            return Err(ReplayError::NotAReplay);
+        }
+        match value.get("version").and_then(Value::as_u64) {
+            Some(VERSION) => (),
+            Some(version) => return Err(ReplayError::Version(version)),
+            None => return Err(ReplayError::NotAReplay),
+        }
+        let syscalls = value.get("syscalls").and_then(Value::as_array);
+        let syscalls = syscalls
+            .ok_or(ReplayError::NotAReplay)?
+            .iter()
+            .enumerate()
+            .map(|(i, syscall)| parse_record(syscall).ok_or(ReplayError::Malformed(i)))
+            .collect::<Result<_, _>>()?;
+        Ok(Self { syscalls })
+    }
+    pub fn save(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(&self.to_json())?)
+    }
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, ReplayError> {
+        Self::from_json(&serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+}
+
+fn parse_record(syscall: &Value) -> Option<SyscallRecord> {
+    let word = |value: &Value, name| u16::try_from(value.get(name)?.as_u64()?).ok();
+    let result = match syscall.get("result")?.as_str()? {
+        "continue" => Recorded::Continue,
+        "exit" => Recorded::Exit(u8::try_from(syscall.get("code")?.as_u64()?).ok()?),
+        "fault" => Recorded::Fault,
+        _ => return None,
+    };
+    let writes = syscall
+        .get("writes")?
+        .as_array()?
+        .iter()
+        .map(|write| {
+            let bytes = unhex(write.get("bytes")?.as_str()?)?;
+            Some((word(write, "address")?, bytes.into()))
+        })
+        .collect::<Option<_>>()?;
+    Some(SyscallRecord {
+        number: word(syscall, "number")?,
+        rax: word(syscall, "rax")?,
+        result,
+        writes,
+    })
+}
+
+impl BasmVM {
+    /// makes the syscall in rax, recording or replaying it if asked to
+    pub(crate) fn syscall(&mut self) -> SyscallResult {
+        match self.replay.take() {
+            None => self.call_handler(),
+            Some(Replay::Record(mut log)) => {
+                let number = self.reg(Register::RAX);
+                // the writes of the syscall alone, passed on to any outer journal
+                let outer = self.journal.replace(Vec::new());
+                let res = self.call_handler();
+                let writes = std::mem::replace(&mut self.journal, outer).unwrap_or_default();
+                if let Some(journal) = &mut self.journal {
+                    journal.extend(writes.iter().cloned());
+                }
+                log.syscalls.push(SyscallRecord {
+                    number,
+                    rax: self.reg(Register::RAX),
+                    result: match res {
+                        SyscallResult::Continue => Recorded::Continue,
+                        SyscallResult::Exit(code) => Recorded::Exit(code),
+                        SyscallResult::Fault(_) => Recorded::Fault,
+                    },
+                    writes: writes.into_iter().map(|w| (w.address, w.new)).collect(),
+                });
+                self.replay = Some(Replay::Record(log));
+                res
+            }
+            Some(Replay::Play { log, mut next }) => {
+                let res = match log.syscalls.get(next) {
+                    Some(record) => self.play(record),
+                    None => SyscallResult::Fault(FaultKind::ReplayDiverged {
+                        expected: None,
+                        found: self.reg(Register::RAX),
+                    }),
+                };
+                next += 1;
+                self.replay = Some(Replay::Play { log, next });
+                res
+            }
+        }
+    }
+
+    fn call_handler(&mut self) -> SyscallResult {
+        // the table is taken out so handlers may borrow the whole vm
+        let mut syscalls = std::mem::replace(&mut self.syscalls, Syscalls::empty());
+        let res = syscalls.call(self);
+        self.syscalls = syscalls;
+        res
+    }
+
+    /// repeats a recorded syscall, without making it
+    fn play(&mut self, record: &SyscallRecord) -> SyscallResult {
+        let number = self.reg(Register::RAX);
+        if number != record.number {
+            return SyscallResult::Fault(FaultKind::ReplayDiverged {
+                expected: Some(record.number),
+                found: number,
+            });
+        }
+        let count = match record.result {
+            Recorded::Fault => return self.call_handler(),
+            Recorded::Continue | Recorded::Exit(_) => record.rax,
+        };
+        // output is not an input to the program, so it is written again
+        let fd = self.reg(Register::RDI);
+        if number == SYS_WRITE && (1..=2).contains(&fd) && (count as i16) > 0 {
+            let buf = self.reg(Register::RSI);
+            if let Ok(bytes) = self.slice(buf, count).map(<[u8]>::to_vec) {
+                let _ = self.io.write(fd, &bytes);
+            }
+        }
+        for (address, bytes) in &record.writes {
+            if let Err(kind) = self.write_bytes(*address, bytes) {
+                return SyscallResult::Fault(kind);
+            }
+        }
+        self.set_reg(Register::RAX, record.rax);
+        match record.result {
+            Recorded::Exit(code) => SyscallResult::Exit(code),
+            _ => SyscallResult::Continue,
+        }
+    }
+}
@@ -0,0 +1,319 @@
+use std::path::Path;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use crate::backtrace::Frame;
+use crate::fs::Vfs;
+use crate::symbols::{Symbols, Variable};
+use crate::{BasmVM, Flag, Register, StepEvent, VmOptions, MEM_SIZE, REGISTER_COUNT};
+
+/// names the kind of document a snapshot is
+pub const FORMAT: &str = "basm-vm snapshot";
+/// the newest snapshot version this vm reads & the one it writes
+///
+/// version 1 snapshots have no calls, image or halt, so they restore with no
+/// calls outstanding, still running & with their memory as the image
+pub const VERSION: u64 = 2;
+
+/// memory is stored in pages of this many bytes
+const PAGE: usize = 64;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    /// the document is json, but not a snapshot
+    NotASnapshot,
+    /// the snapshot has a version this vm does not know
+    Version(u64),
+    /// a field is missing or holds the wrong kind of value
+    Field(String),
+    /// the files were on the host, but no directory was given for them
+    HostRoot,
+}
+
+impl std::fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Io(err) => write!(f, "{err}"),
+            Self::Json(err) => write!(f, "invalid json: {err}"),
+            Self::NotASnapshot => write!(f, "not a {FORMAT}"),
+            Self::Version(version) => {
+                write!(
+                    f,
+                    "unsupported snapshot version {version}, expected {VERSION}"
+                )
+            }
+            Self::Field(name) => write!(f, "missing or malformed field `{name}`"),
+            Self::HostRoot => write!(
+                f,
+                "the snapshot's files are on the host, but no root was given"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SnapshotError {}
+
+impl From<std::io::Error> for SnapshotError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for SnapshotError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Json(err)
+    }
+}
+
+impl BasmVM {
+    /// the state of the vm as a self describing json document
+    ///
+    /// the streams, syscalls & any tracer, profiler or history are not part
+    /// of a snapshot, nor is a fault, as resuming runs the faulting
+    /// instruction again
+    pub fn snapshot(&self) -> Value {
+        let registers: serde_json::Map<_, _> = registers()
+            .map(|r| (r.to_string(), json!(self.reg(r))))
+            .collect();
+        let flags: Vec<_> = Flag::ALL
+            .into_iter()
+            .filter(|&f| self.flag(f))
+            .map(|f| format!("{f:?}"))
+            .collect();
+        let halted = match self.halted {
+            Some(StepEvent::Exited(code)) => json!({ "exited": code }),
+            Some(StepEvent::End) => json!("end"),
+            _ => Value::Null,
+        };
+        let symbols = &self.symbols;
+        let labels: Vec<_> = symbols
+            .labels
+            .iter()
+            .map(|(name, address)| json!({ "name": name, "address": address }))
+            .collect();
+        let variables: Vec<_> = symbols
+            .variables
+            .iter()
+            .map(|var| json!({ "name": var.name, "address": var.address, "len": var.len }))
+            .collect();
+        let calls: Vec<_> = self
+            .calls
+            .iter()
+            .map(|frame| json!({ "call": frame.call, "slot": frame.slot, "target": frame.target }))
+            .collect();
+        let options = &self.options;
+        json!({
+            "format": FORMAT,
+            "version": VERSION,
+            "rip": self.rip,
+            "executed": self.executed,
+            "registers": registers,
+            "flags": flags,
+            "memory": pages(&self.mem),
+            "image": pages(&self.image),
+            "calls": calls,
+            "halted": halted,
+            "fs": self.fs.to_json(),
+            "options": {
+                "trap_overflow": options.trap_overflow,
+                "max_instructions": options.max_instructions,
+                "time_limit_ms": options.time_limit.map(|limit| limit.as_millis() as u64),
+                "history": options.history,
+            },
+            "symbols": {
+                "code_start": symbols.code_start,
+                "labels": labels,
+                "variables": variables,
+                "lines": symbols.lines,
+            },
+        })
+    }
+    /// writes a snapshot to `path`
+    pub fn save_snapshot(&self, path: impl AsRef<Path>) -> std::io::Result<()> {
+        let snapshot = serde_json::to_string_pretty(&self.snapshot())?;
+        std::fs::write(path, snapshot)
+    }
+    /// rebuilds a vm from [`BasmVM::snapshot`], ready to carry on from where
+    /// it was taken with [`BasmVM::resume`]
+    ///
+    /// files on the host are opened within `host`, which a snapshot taken with
+    /// a host root needs
+    pub fn restore(snapshot: &Value, host: Option<&Path>) -> Result<Self, SnapshotError> {
+        if snapshot.get("format").and_then(Value::as_str) != Some(FORMAT) {
+            return Err(SnapshotError::NotASnapshot);
+        }
+        let version = match uint(snapshot, "version")? {
+            version @ (1 | VERSION) => version,
+            version => return Err(SnapshotError::Version(version)),
+        };
+        let mem = restore_pages(snapshot, "memory")?;
+        let mut vm = BasmVM::new(mem, restore_symbols(field(snapshot, "symbols")?)?);
+        let values = field(snapshot, "registers")?;
+        for r in registers() {
+            vm.set_reg(r, word(values, &r.to_string())?);
+        }
+        for name in array(snapshot, "flags")? {
+            let flag = Flag::ALL
+                .into_iter()
+                .find(|f| name.as_str() == Some(&format!("{f:?}")));
+            let flag = flag.ok_or_else(|| SnapshotError::Field("flags".to_string()))?;
+            vm.set_flag(flag, true);
+        }
+        vm.rip = word(snapshot, "rip")?;
+        vm.executed = uint(snapshot, "executed")?;
+        // version 1 kept no calls, nor how the program was loaded or stopped
+        if version > 1 {
+            for frame in array(snapshot, "calls")? {
+                vm.calls.push(Frame {
+                    call: word(frame, "call")?,
+                    slot: word(frame, "slot")?,
+                    target: word(frame, "target")?,
+                });
+            }
+            vm.image = Box::new(restore_pages(snapshot, "image")?);
+            vm.halted = match field(snapshot, "halted")? {
+                Value::Null => None,
+                Value::String(end) if end == "end" => Some(StepEvent::End),
+                halted => {
+                    let code = u8::try_from(uint(halted, "exited")?);
+                    let code =
+                        code.map_err(|_| SnapshotError::Field("halted.exited".to_string()))?;
+                    Some(StepEvent::Exited(code))
+                }
+            };
+        }
+        vm.fs = Vfs::from_json(field(snapshot, "fs")?, host)?;
+        let options = field(snapshot, "options")?;
+        let bad = |name: &str| SnapshotError::Field(format!("options.{name}"));
+        vm.options = VmOptions {
+            trap_overflow: field(options, "trap_overflow")?
+                .as_bool()
+                .ok_or_else(|| bad("trap_overflow"))?,
+            max_instructions: optional(options, "max_instructions")?,
+            time_limit: optional(options, "time_limit_ms")?.map(Duration::from_millis),
+            history: uint(options, "history")? as usize,
+        };
+        Ok(vm)
+    }
+    /// reads a snapshot written by [`BasmVM::save_snapshot`]
+    pub fn load_snapshot(
+        path: impl AsRef<Path>,
+        host: Option<&Path>,
+    ) -> Result<Self, SnapshotError> {
+        let snapshot = serde_json::from_slice(&std::fs::read(path)?)?;
+        Self::restore(&snapshot, host)
+    }
+}
+
+/// memory in pages, leaving out those that are all zero
+fn pages(mem: &[u8]) -> Vec<Value> {
+    mem.chunks(PAGE)
+        .enumerate()
+        .filter(|(_, page)| page.iter().any(|&b| b != 0))
+        .map(|(i, page)| json!({ "address": i * PAGE, "bytes": hex(page) }))
+        .collect()
+}
+
+/// the inverse of [`pages`], for the pages in the field `name`
+fn restore_pages(snapshot: &Value, name: &str) -> Result<[u8; MEM_SIZE], SnapshotError> {
+    let mut mem = [0; MEM_SIZE];
+    for page in array(snapshot, name)? {
+        let address = uint(page, "address")? as usize;
+        let bytes = field(page, "bytes")?.as_str().and_then(unhex);
+        let bytes = bytes.ok_or_else(|| SnapshotError::Field(format!("{name}.bytes")))?;
+        let dest = address
+            .checked_add(bytes.len())
+            .and_then(|end| mem.get_mut(address..end));
+        let Some(dest) = dest else {
+            return Err(SnapshotError::Field(format!("{name}.address")));
+        };
+        dest.copy_from_slice(&bytes);
+    }
+    Ok(mem)
+}
+
+fn restore_symbols(symbols: &Value) -> Result<Symbols, SnapshotError> {
+    let labels = array(symbols, "labels")?
+        .iter()
+        .map(|label| Ok((string(label, "name")?, word(label, "address")?)))
+        .collect::<Result<_, SnapshotError>>()?;
+    let variables = array(symbols, "variables")?
+        .iter()
+        .map(|var| {
+            Ok(Variable {
+                name: string(var, "name")?,
+                address: word(var, "address")?,
+                len: word(var, "len")?,
+            })
+        })
+        .collect::<Result<_, SnapshotError>>()?;
+    let lines = array(symbols, "lines")?
+        .iter()
+        .map(|line| line.as_u64().and_then(|line| u32::try_from(line).ok()))
+        .collect::<Option<_>>()
+        .ok_or_else(|| SnapshotError::Field("symbols.lines".to_string()))?;
+    Ok(Symbols {
+        labels,
+        variables,
+        lines,
+        code_start: word(symbols, "code_start")?,
+    })
+}
+
+fn registers() -> impl Iterator<Item = Register> {
+    (0..REGISTER_COUNT as u16).filter_map(|r| Register::try_from(r).ok())
+}
+
+pub(crate) fn field<'a>(value: &'a Value, name: &str) -> Result<&'a Value, SnapshotError> {
+    value
+        .get(name)
+        .ok_or_else(|| SnapshotError::Field(name.to_string()))
+}
+
+pub(crate) fn uint(value: &Value, name: &str) -> Result<u64, SnapshotError> {
+    field(value, name)?
+        .as_u64()
+        .ok_or_else(|| SnapshotError::Field(name.to_string()))
+}
+
+fn word(value: &Value, name: &str) -> Result<u16, SnapshotError> {
+    u16::try_from(uint(value, name)?).map_err(|_| SnapshotError::Field(name.to_string()))
+}
+
+fn string(value: &Value, name: &str) -> Result<String, SnapshotError> {
+    field(value, name)?
+        .as_str()
+        .map(str::to_string)
+        .ok_or_else(|| SnapshotError::Field(name.to_string()))
+}
+
+fn array<'a>(value: &'a Value, name: &str) -> Result<&'a Vec<Value>, SnapshotError> {
+    field(value, name)?
+        .as_array()
+        .ok_or_else(|| SnapshotError::Field(name.to_string()))
+}
+
+/// a number which may be null
+fn optional(value: &Value, name: &str) -> Result<Option<u64>, SnapshotError> {
+    match field(value, name)? {
+        Value::Null => Ok(None),
+        _ => uint(value, name).map(Some),
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub(crate) fn unhex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,74 @@
+use crate::INS_SIZE;
+
+/// where the labels, variables & source lines of a program ended up in memory
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Symbols {
+    /// labels with their addresses, sorted by address
+    pub labels: Vec<(String, u16)>,
+    /// variables sorted by address
+    pub variables: Vec<Variable>,
+    /// the source line of each instruction, counting from zero
+    pub lines: Vec<u32>,
+    pub code_start: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variable {
+    pub name: String,
+    pub address: u16,
+    pub len: u16,
+}
+
+impl Symbols {
+    pub fn label(&self, name: &str) -> Option<u16> {
+        self.labels
+            .iter()
+            .find_map(|(label, address)| (label == name).then_some(*address))
+    }
+    pub fn variable(&self, name: &str) -> Option<&Variable> {
+        self.variables.iter().find(|var| var.name == name)
+    }
+    /// the source line of the instruction at `address`
+    pub fn line(&self, address: u16) -> Option<u32> {
+        let offset = address.checked_sub(self.code_start)?;
+        if !offset.is_multiple_of(INS_SIZE) {
+            return None;
+        }
+        self.lines.get((offset / INS_SIZE) as usize).copied()
+    }
+    /// the address of the first instruction on or after `line`
+    pub fn line_address(&self, line: u32) -> Option<u16> {
+        let index = self.lines.iter().position(|&l| l >= line)?;
+        Some(self.code_start + index as u16 * INS_SIZE)
+    }
+    /// the closest label at or before `address`, along with the offset from it
+    pub fn enclosing_label(&self, address: u16) -> Option<(&str, u16)> {
+        self.labels
+            .iter()
+            .rev()
+            .find(|(_, at)| *at <= address)
+            .map(|(label, at)| (label.as_str(), address - at))
+    }
+    /// a readable location, e.g. `print+8 (line 12)`
+    pub fn describe(&self, address: u16) -> String {
+        let mut out = match self.enclosing_label(address) {
+            Some((label, 0)) => label.to_string(),
+            Some((label, offset)) => format!("{label}+{offset}"),
+            None => format!("{address:#06x}"),
+        };
+        if let Some(line) = self.line(address) {
+            out.push_str(&format!(" (line {})", line + 1));
+        }
+        out
+    }
+    /// one numbered line per frame of [`BasmVM::backtrace`], innermost first
+    ///
+    /// [`BasmVM::backtrace`]: crate::BasmVM::backtrace
+    pub fn backtrace(&self, frames: &[u16]) -> String {
+        frames
+            .iter()
+            .enumerate()
+            .map(|(i, &address)| format!("#{i} {address:#06x} {}\n", self.describe(address)))
+            .collect()
+    }
+}
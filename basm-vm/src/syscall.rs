@@ -0,0 +1,180 @@
+use ahash::AHashMap;
+
+use crate::fs::Errno;
+use crate::{BasmVM, FaultKind, Register};
+
+pub const SYS_READ: u16 = 0x00;
+pub const SYS_WRITE: u16 = 0x01;
+pub const SYS_OPEN: u16 = 0x02;
+pub const SYS_CLOSE: u16 = 0x03;
+pub const SYS_EXIT: u16 = 0x3c;
+
+/// what the vm should do once a syscall has been handled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyscallResult {
+    Continue,
+    Exit(u8),
+    Fault(FaultKind),
+}
+
+impl From<Result<(), FaultKind>> for SyscallResult {
+    fn from(res: Result<(), FaultKind>) -> Self {
+        match res {
+            Ok(()) => SyscallResult::Continue,
+            Err(kind) => SyscallResult::Fault(kind),
+        }
+    }
+}
+
+/// a syscall, which is given the whole vm when called
+///
+/// arguments are read from rdi, rsi & rdx, the result is left in rax
+pub trait SyscallHandler: Send {
+    fn call(&mut self, vm: &mut BasmVM) -> SyscallResult;
+}
+
+impl<F> SyscallHandler for F
+where
+    F: FnMut(&mut BasmVM) -> SyscallResult + Send,
+{
+    fn call(&mut self, vm: &mut BasmVM) -> SyscallResult {
+        self(vm)
+    }
+}
+
+/// the table of syscalls, keyed by the number in rax
+pub struct Syscalls {
+    handlers: AHashMap<u16, Box<dyn SyscallHandler>>,
+}
+
+impl Default for Syscalls {
+    fn default() -> Self {
+        Self::linux()
+    }
+}
+
+impl std::fmt::Debug for Syscalls {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut numbers: Vec<_> = self.handlers.keys().collect();
+        numbers.sort();
+        f.debug_struct("Syscalls")
+            .field("handlers", &numbers)
+            .finish()
+    }
+}
+
+impl Syscalls {
+    /// a table without any syscalls
+    pub fn empty() -> Self {
+        Self {
+            handlers: AHashMap::new(),
+        }
+    }
+    /// the linux x86-64 syscalls the vm supports
+    pub fn linux() -> Self {
+        let mut syscalls = Self::empty();
+        syscalls.register(SYS_READ, sys_read);
+        syscalls.register(SYS_WRITE, sys_write);
+        syscalls.register(SYS_OPEN, sys_open);
+        syscalls.register(SYS_CLOSE, sys_close);
+        syscalls.register(SYS_EXIT, sys_exit);
+        syscalls
+    }
+    /// adds a syscall, returning the handler it replaced
+    pub fn register(
+        &mut self,
+        number: u16,
+        handler: impl SyscallHandler + 'static,
+    ) -> Option<Box<dyn SyscallHandler>> {
+        self.handlers.insert(number, Box::new(handler))
+    }
+    pub fn remove(&mut self, number: u16) -> Option<Box<dyn SyscallHandler>> {
+        self.handlers.remove(&number)
+    }
+    pub fn contains(&self, number: u16) -> bool {
+        self.handlers.contains_key(&number)
+    }
+    pub(crate) fn call(&mut self, vm: &mut BasmVM) -> SyscallResult {
+        let number = vm.reg(Register::RAX);
+        match self.handlers.get_mut(&number) {
+            Some(handler) => handler.call(vm),
+            None => SyscallResult::Fault(FaultKind::BadSyscall(number)),
+        }
+    }
+}
+
+/// `read(fd, buf, count)`
+pub fn sys_read(vm: &mut BasmVM) -> SyscallResult {
+    let fd = vm.reg(Register::RDI);
+    let buf = vm.reg(Register::RSI);
+    let count = vm.reg(Register::RDX);
+    if let Err(kind) = vm.slice(buf, count) {
+        return SyscallResult::Fault(kind);
+    }
+    let mut bytes = vec![0; count as usize];
+    let res = match fd {
+        0..=2 => vm.io.read(fd, &mut bytes),
+        _ => vm.fs.read(fd, &mut bytes),
+    };
+    if let Ok(read) = res {
+        if let Err(kind) = vm.write_bytes(buf, &bytes[..read as usize]) {
+            return SyscallResult::Fault(kind);
+        }
+    }
+    ret(vm, res)
+}
+
+/// `write(fd, buf, count)`
+pub fn sys_write(vm: &mut BasmVM) -> SyscallResult {
+    let fd = vm.reg(Register::RDI);
+    let buf = vm.reg(Register::RSI);
+    let count = vm.reg(Register::RDX);
+    let bytes = match vm.slice(buf, count) {
+        Ok(bytes) => bytes.to_vec(),
+        Err(kind) => return SyscallResult::Fault(kind),
+    };
+    let res = match fd {
+        0..=2 => vm.io.write(fd, &bytes),
+        _ => vm.fs.write(fd, &bytes),
+    };
+    ret(vm, res)
+}
+
+/// `open(path, flags, mode)`, where the mode is ignored
+pub fn sys_open(vm: &mut BasmVM) -> SyscallResult {
+    let path = match vm.c_str(vm.reg(Register::RDI)) {
+        Ok(path) => String::from_utf8_lossy(path).into_owned(),
+        Err(kind) => return SyscallResult::Fault(kind),
+    };
+    let flags = vm.reg(Register::RSI);
+    let res = vm.fs.open(&path, flags);
+    ret(vm, res)
+}
+
+/// `close(fd)`
+pub fn sys_close(vm: &mut BasmVM) -> SyscallResult {
+    let res = vm.fs.close(vm.reg(Register::RDI)).map(|_| 0);
+    ret(vm, res)
+}
+
+/// `exit(code)`
+pub fn sys_exit(vm: &mut BasmVM) -> SyscallResult {
+    SyscallResult::Exit(vm.reg(Register::RDI) as u8)
+}
+
+/// prints rdi as a signed decimal followed by a newline
+///
+/// not part of the default table, for teaching it can be registered with
+/// [`Syscalls::register`] under a number of your choosing
+pub fn print_int(vm: &mut BasmVM) -> SyscallResult {
+    let line = format!("{}\n", vm.reg(Register::RDI) as i16);
+    let res = vm.io.write(1, line.as_bytes());
+    ret(vm, res)
+}
+
+/// leaves a result in rax, with errors as a negated errno
+fn ret(vm: &mut BasmVM, res: Result<u16, Errno>) -> SyscallResult {
+    let rax = res.unwrap_or_else(|errno| errno.wrapping_neg());
+    vm.set_reg(Register::RAX, rax);
+    SyscallResult::Continue
+}
@@ -0,0 +1,1993 @@
+use expect_test::{expect, Expect};
+
+use crate::fs::Vfs;
+use crate::io::{Captured, Scripted, VmIo};
+use crate::replay::{Replay, ReplayLog};
+use crate::syscall::{print_int, SyscallResult};
+use crate::{BasmVM, Budget, FaultKind, Flag, Register, StepEvent, Termination, Width};
+
+fn parse(src: &str) -> BasmVM {
+    let Ok(vm) = BasmVM::parse(src) else {
+        panic!("failed to parse test program");
+    };
+    vm
+}
+
+fn check(src: &str, expect: Expect) {
+    check_vm(parse(src), expect);
+}
+
+fn check_vm(mut vm: BasmVM, expect: Expect) {
+    let outcome = vm.run();
+    let fault = match outcome.reason {
+        Termination::Exited | Termination::End => String::new(),
+        Termination::BudgetExhausted(budget) => {
+            format!(
+                "BudgetExhausted({budget:?}) at {:#06x} ",
+                outcome.registers.rip
+            )
+        }
+        Termination::Faulted(err) => format!("{:?} at {:#06x} ", err.kind, err.address),
+    };
+    let regs: Vec<_> = (0..16)
+        .filter_map(|r| Register::try_from(r).ok())
+        .filter(|&r| !matches!(r, Register::RSP) && vm.reg(r) != 0)
+        .map(|r| format!("{r:?}={}", vm.reg(r)))
+        .collect();
+    let flags: Vec<_> = Flag::ALL.into_iter().filter(|&f| vm.flag(f)).collect();
+    expect.assert_eq(&format!("{fault}{} {flags:?}", regs.join(" ")));
+}
+
+#[test]
+fn falls_off_end() {
+    check(
+        "
+_start:
+    mov rax, 3
+    mov rbx, rax",
+        expect!["RAX=3 RBX=3 []"],
+    );
+}
+
+#[test]
+fn call_ret() {
+    check(
+        "
+_start:
+    call set
+    inc rbx
+    mov rax, 60
+    syscall
+set:
+    mov rbx, 5
+    ret",
+        expect!["RAX=60 RBX=6 [Pf]"],
+    );
+}
+
+#[test]
+fn nested_call() {
+    check(
+        "
+_start:
+    call outer
+    mov rax, 60
+    syscall
+outer:
+    call inner
+    inc rcx
+    ret
+inner:
+    mov rcx, 10
+    ret",
+        expect!["RAX=60 RCX=11 []"],
+    );
+}
+
+#[test]
+fn start_not_first() {
+    check(
+        "
+skipped:
+    mov rbx, 1
+_start:
+    mov rax, 60
+    syscall",
+        expect!["RAX=60 []"],
+    );
+}
+
+#[test]
+fn stack_restored_after_call() {
+    check(
+        "
+_start:
+    push 7
+    call noop
+    pop rdx
+    mov rax, 60
+    syscall
+noop:
+    ret",
+        expect!["RAX=60 RDX=7 []"],
+    );
+}
+
+#[test]
+fn cmp_equal() {
+    check(
+        "
+_start:
+    mov rax, 5
+    cmp rax, 5",
+        expect!["RAX=5 [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn cmp_below() {
+    check(
+        "
+_start:
+    mov rax, 3
+    cmp rax, 5",
+        expect!["RAX=3 [Sf, Cf, Af]"],
+    );
+}
+
+#[test]
+fn add_carry() {
+    check(
+        "
+_start:
+    mov rax, 65535
+    add rax, 1",
+        expect![" [Zf, Cf, Af, Pf]"],
+    );
+}
+
+#[test]
+fn add_overflow() {
+    check(
+        "
+_start:
+    mov rax, 32767
+    add rax, 1",
+        expect!["RAX=32768 [Sf, Af, Pf, Of]"],
+    );
+}
+
+#[test]
+fn sub_overflow() {
+    check(
+        "
+_start:
+    mov rax, 32768
+    sub rax, 1",
+        expect!["RAX=32767 [Af, Pf, Of]"],
+    );
+}
+
+#[test]
+fn inc_keeps_carry() {
+    check(
+        "
+_start:
+    mov rax, 0
+    sub rax, 1
+    inc rbx",
+        expect!["RAX=65535 RBX=1 [Cf]"],
+    );
+}
+
+#[test]
+fn logic_clears_carry() {
+    check(
+        "
+_start:
+    mov rax, 0
+    sub rax, 1
+    and rax, 240",
+        expect!["RAX=240 [Pf]"],
+    );
+}
+
+#[test]
+fn jne_loop() {
+    check(
+        "
+_start:
+    mov rcx, 5
+loop:
+    add rax, 2
+    dec rcx
+    cmp rcx, 0
+    jne loop",
+        expect!["RAX=10 [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn jmp_skips() {
+    check(
+        "
+_start:
+    jmp done
+    mov rax, 1
+done:
+    mov rbx, 2",
+        expect!["RBX=2 []"],
+    );
+}
+
+#[test]
+fn jz_alias() {
+    check(
+        "
+_start:
+    xor rax, rax
+    jz done
+    mov rbx, 1
+done:",
+        expect![" [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn signed_jumps() {
+    check(
+        "
+_start:
+    mov rax, 65535
+    cmp rax, 1
+    jl less
+    mov rbx, 1
+less:
+    cmp rax, 65535
+    jle less_equal
+    mov rbx, 2
+less_equal:
+    cmp rax, 65534
+    jg greater
+    mov rbx, 3
+greater:
+    cmp rax, 65535
+    jge greater_equal
+    mov rbx, 4
+greater_equal:",
+        expect!["RAX=65535 [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn unsigned_jumps() {
+    check(
+        "
+_start:
+    mov rax, 1
+    cmp rax, 65535
+    jb below
+    mov rbx, 1
+below:
+    cmp rax, 1
+    jbe below_equal
+    mov rbx, 2
+below_equal:
+    cmp rax, 0
+    ja above
+    mov rbx, 3
+above:
+    cmp rax, 1
+    jae above_equal
+    mov rbx, 4
+above_equal:",
+        expect!["RAX=1 [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn untaken_jumps() {
+    check(
+        "
+_start:
+    mov rax, 1
+    cmp rax, 65535
+    jl signed
+    ja unsigned
+    mov rbx, 1
+signed:
+    mov rcx, 1
+unsigned:
+    mov rdx, 1",
+        expect!["RAX=1 RBX=1 RCX=1 RDX=1 [Cf, Af]"],
+    );
+}
+
+#[test]
+fn mul_wide() {
+    check(
+        "
+_start:
+    mov rax, 300
+    mov rbx, 300
+    mul rbx",
+        expect!["RAX=24464 RBX=300 RDX=1 [Cf, Of]"],
+    );
+}
+
+#[test]
+fn imul_negative() {
+    check(
+        "
+_start:
+    mov rax, 65535
+    imul 3",
+        expect!["RAX=65533 RDX=65535 []"],
+    );
+}
+
+#[test]
+fn div_remainder() {
+    check(
+        "
+_start:
+    mov rax, 1337
+    mov rbx, 10
+    div rbx",
+        expect!["RAX=133 RBX=10 RDX=7 []"],
+    );
+}
+
+#[test]
+fn idiv_negative() {
+    check(
+        "
+_start:
+    mov rax, 65529
+    mov rdx, 65535
+    mov rbx, 2
+    idiv rbx",
+        expect!["RAX=65533 RBX=2 RDX=65535 []"],
+    );
+}
+
+#[test]
+fn div_by_zero_stops() {
+    check(
+        "
+_start:
+    mov rax, 1
+    div rbx
+    mov rcx, 1",
+        expect!["DivisionByZero at 0x000e RAX=1 []"],
+    );
+}
+
+#[test]
+fn div_overflow_stops() {
+    check(
+        "
+_start:
+    mov rdx, 1
+    div 1
+    mov rcx, 1",
+        expect!["DivisionOverflow at 0x000e RDX=1 []"],
+    );
+}
+
+#[test]
+fn neg_not() {
+    check(
+        "
+_start:
+    mov rax, 1
+    neg rax
+    mov rbx, 255
+    not rbx",
+        expect!["RAX=65535 RBX=65280 [Sf, Cf, Af, Pf]"],
+    );
+}
+
+#[test]
+fn shl_carry() {
+    check(
+        "
+_start:
+    mov rax, 49153
+    shl rax, 1",
+        expect!["RAX=32770 [Sf, Cf]"],
+    );
+}
+
+#[test]
+fn shr_by_register() {
+    check(
+        "
+_start:
+    mov rax, 100
+    mov rcx, 3
+    shr rax, rcx",
+        expect!["RAX=12 RCX=3 [Cf, Pf]"],
+    );
+}
+
+#[test]
+fn sar_keeps_sign() {
+    check(
+        "
+_start:
+    mov rax, 65520
+    sar rax, 2",
+        expect!["RAX=65532 [Sf, Pf]"],
+    );
+}
+
+#[test]
+fn shift_by_zero_keeps_flags() {
+    check(
+        "
+_start:
+    mov rax, 5
+    cmp rax, 5
+    shl rax, 0",
+        expect!["RAX=5 [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn rotates() {
+    check(
+        "
+_start:
+    mov rax, 32769
+    rol rax, 1
+    mov rbx, 1
+    ror rbx, 1",
+        expect!["RAX=3 RBX=32768 [Cf, Of]"],
+    );
+}
+
+#[test]
+fn partial_writes() {
+    check(
+        "
+_start:
+    mov rax, 4660
+    mov al, 255
+    mov rbx, 4660
+    mov bh, 0
+    mov cx, 7",
+        expect!["RAX=4863 RBX=52 RCX=7 []"],
+    );
+}
+
+#[test]
+fn byte_arithmetic() {
+    check(
+        "
+_start:
+    mov rax, 511
+    add al, 1",
+        expect!["RAX=256 [Zf, Cf, Af, Pf]"],
+    );
+}
+
+#[test]
+fn byte_cmp() {
+    check(
+        "
+_start:
+    mov rcx, 256
+    cmp cl, 0",
+        expect!["RCX=256 [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn byte_mul_div() {
+    check(
+        "
+_start:
+    mov rax, 20
+    mov rbx, 13
+    mul bl
+    mov rdx, 7
+    div dl",
+        expect!["RAX=293 RBX=13 RDX=7 [Cf, Of]"],
+    );
+}
+
+#[test]
+fn high_byte_read() {
+    check(
+        "
+_start:
+    mov rax, 4660
+    mov bl, ah",
+        expect!["RAX=4660 RBX=18 []"],
+    );
+}
+
+#[test]
+fn string_walk() {
+    check(
+        r#"
+text str "abc", 0
+_start:
+    mov rax, text
+    mov rbx, 0
+loop:
+    mov cl, [rax]
+    cmp cl, 0
+    je done
+    inc rax
+    inc rbx
+    jmp loop
+done:
+    mov rax, 0"#,
+        expect!["RBX=3 [Zf, Pf]"],
+    );
+}
+
+#[test]
+fn word_little_endian() {
+    check(
+        "
+buf bss 4
+_start:
+    mov rax, 4660
+    mov [buf], rax
+    mov bl, [buf]
+    mov rdx, buf
+    inc rdx
+    mov cl, [rdx]",
+        expect!["RAX=4660 RBX=52 RCX=18 RDX=9 [Pf]"],
+    );
+}
+
+#[test]
+fn byte_store() {
+    check(
+        "
+buf bss 2
+_start:
+    mov rax, 65535
+    mov [buf], rax
+    mov rbx, 0
+    mov [buf], bl
+    mov rcx, [buf]",
+        expect!["RAX=65535 RCX=65280 []"],
+    );
+}
+
+#[test]
+fn overflow_wraps() {
+    check(
+        "
+_start:
+    mov rax, 32767
+    inc rax
+    mov rbx, 1",
+        expect!["RAX=32768 RBX=1 [Sf, Af, Pf, Of]"],
+    );
+}
+
+#[test]
+fn overflow_traps() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 32767
+    inc rax
+    mov rbx, 1",
+    );
+    vm.options.trap_overflow = true;
+    check_vm(vm, expect!["Overflow at 0x000e RAX=32767 [Sf, Af, Pf, Of]"]);
+}
+
+#[test]
+fn unsigned_wrap_does_not_trap() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 65535
+    add rax, 1
+    mov rbx, 1",
+    );
+    vm.options.trap_overflow = true;
+    check_vm(vm, expect!["RBX=1 [Zf, Cf, Af, Pf]"]);
+}
+
+#[test]
+fn bad_syscall() {
+    check(
+        "
+_start:
+    mov rax, 999
+    syscall",
+        expect!["BadSyscall(999) at 0x000e RAX=999 []"],
+    );
+}
+
+#[test]
+fn pop_empty_stack() {
+    check(
+        "
+_start:
+    pop rax",
+        expect!["StackUnderflow at 0x0006  []"],
+    );
+}
+
+#[test]
+fn stack_overflow() {
+    check(
+        "
+_start:
+    call _start",
+        expect!["StackOverflow at 0x0006  []"],
+    );
+}
+
+#[test]
+fn memory_out_of_range() {
+    check(
+        "
+_start:
+    mov rax, 65535
+    mov rbx, [rax]",
+        expect!["MemoryOutOfRange(65535) at 0x000e RAX=65535 []"],
+    );
+}
+
+#[test]
+fn illegal_jump_target() {
+    check(
+        "
+_start:
+    mov rax, 3
+    jmp rax",
+        expect!["IllegalInstruction at 0x000e RAX=3 []"],
+    );
+}
+
+#[test]
+fn jump_past_code() {
+    check(
+        "
+_start:
+    mov rax, 4000
+    jmp rax",
+        expect!["IllegalInstruction at 0x000e RAX=4000 []"],
+    );
+}
+
+#[test]
+fn ret_past_code() {
+    check(
+        "
+_start:
+    push 4000
+    ret",
+        expect!["IllegalInstruction at 0x000e  []"],
+    );
+}
+
+#[test]
+fn call_past_code() {
+    check(
+        "
+_start:
+    mov rax, 4000
+    call rax",
+        expect!["IllegalInstruction at 0x000e RAX=4000 []"],
+    );
+}
+
+#[test]
+fn undefined_jump_target() {
+    // the program is rejected whole, rather than run with later addresses off
+    let res = BasmVM::parse(
+        "
+_start:
+    mov rbx, 1
+    jmp nowhere
+    jmp done
+    mov rbx, 7
+done:
+    mov rax, 60
+    mov rdi, rbx
+    syscall",
+    );
+    let Err(err) = res.map(|_| ()) else {
+        panic!("a program jumping to an undefined label was encoded");
+    };
+    expect![[r#"
+
+        MissingSymbol("nowhere")"#]]
+    .assert_eq(&err.to_string());
+}
+
+#[test]
+fn runtime_error_display() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 1
+    div rbx",
+    );
+    let outcome = vm.run();
+    let Some(err) = outcome.error() else {
+        panic!("expected a fault");
+    };
+    expect![[r#"
+        fault at 0x000e: division by zero
+        rax: 0x0001 rbx: 0x0000 rcx: 0x0000 rdx: 0x0000
+        rsi: 0x0000 rdi: 0x0000 rsp: 0xffff rbp: 0x0000
+        r08: 0x0000 r09: 0x0000 r10: 0x0000 r11: 0x0000
+        r12: 0x0000 r13: 0x0000 r14: 0x0000 r15: 0x0000
+        rip: 0x000e flags: []"#]]
+    .assert_eq(&err.to_string());
+}
+
+fn check_output(src: &str, expect: Expect) {
+    check_output_vm(parse(src), expect);
+}
+
+fn check_output_vm(vm: BasmVM, expect: Expect) {
+    check_input_vm(vm, Scripted::default(), expect);
+}
+
+fn check_input_vm(vm: BasmVM, stdin: Scripted, expect: Expect) {
+    let (stdout, stderr) = (Captured::default(), Captured::default());
+    let io = VmIo::new(stdin, stdout.clone(), stderr.clone());
+    let mut vm = vm.with_io(io);
+    let _ = vm.run();
+    expect.assert_eq(&format!(
+        "stdout: {:?}\nstderr: {:?}\nrax: {}",
+        stdout.to_string_lossy(),
+        stderr.to_string_lossy(),
+        vm.reg(Register::RAX) as i16,
+    ));
+}
+
+#[test]
+fn write_stdout() {
+    check_output(
+        "
+    msg str \"hi\", 10
+_start:
+    mov rax, 1
+    mov rdi, 1
+    mov rsi, msg
+    mov rdx, 3
+    syscall",
+        expect![[r#"
+            stdout: "hi\n"
+            stderr: ""
+            rax: 3"#]],
+    );
+}
+
+#[test]
+fn write_stderr() {
+    check_output(
+        "
+    msg str \"oops\"
+_start:
+    mov rax, 1
+    mov rdi, 2
+    mov rsi, msg
+    mov rdx, 4
+    syscall",
+        expect![[r#"
+            stdout: ""
+            stderr: "oops"
+            rax: 4"#]],
+    );
+}
+
+#[test]
+fn write_bad_fd() {
+    check_output(
+        "
+    msg str \"x\"
+_start:
+    mov rax, 1
+    mov rdi, 7
+    mov rsi, msg
+    mov rdx, 1
+    syscall",
+        expect![[r#"
+            stdout: ""
+            stderr: ""
+            rax: -9"#]],
+    );
+}
+
+#[test]
+fn custom_print_int() {
+    let vm = parse(
+        "
+_start:
+    mov rax, 512
+    mov rdi, 42
+    neg rdi
+    syscall",
+    )
+    .with_syscall(512, print_int);
+    check_output_vm(
+        vm,
+        expect![[r#"
+            stdout: "-42\n"
+            stderr: ""
+            rax: 4"#]],
+    );
+}
+
+#[test]
+fn custom_syscall_state() {
+    let vm = parse(
+        "
+_start:
+    mov rax, 7
+    mov rdi, 5
+    syscall
+    mov rbx, rax
+    mov rax, 7
+    mov rdi, 0
+    syscall
+    mov rax, 60
+    mov rdi, 0
+    syscall",
+    )
+    .with_syscall(7, |vm: &mut BasmVM| match vm.reg(Register::RDI) {
+        0 => SyscallResult::Fault(FaultKind::BadSyscall(7)),
+        n => {
+            vm.set_reg(Register::RAX, n * 2);
+            SyscallResult::Continue
+        }
+    });
+    check_vm(vm, expect!["BadSyscall(7) at 0x0036 RAX=7 RBX=10 []"]);
+}
+
+#[test]
+fn unregistered_syscall() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 60
+    syscall",
+    );
+    vm.syscalls.remove(crate::syscall::SYS_EXIT);
+    check_vm(vm, expect!["BadSyscall(60) at 0x000e RAX=60 []"]);
+}
+
+fn run_fs(src: &str, fs: Vfs) -> BasmVM {
+    let mut vm = parse(src).with_fs(fs);
+    let _ = vm.run();
+    vm
+}
+
+#[test]
+fn write_file() {
+    let vm = run_fs(
+        "
+    name str \"out.txt\", 0
+    text str \"hello\"
+_start:
+    mov rax, 2
+    mov rdi, name
+    mov rsi, 65
+    syscall
+    push rax
+    mov rdi, rax
+    mov rax, 1
+    mov rsi, text
+    mov rdx, 5
+    syscall
+    mov rax, 3
+    pop rdi
+    syscall",
+        Vfs::memory(),
+    );
+    let contents = vm.fs.contents("out.txt").unwrap_or_default();
+    expect![[r#"rax=0 "hello""#]].assert_eq(&format!(
+        "rax={} {:?}",
+        vm.reg(Register::RAX),
+        String::from_utf8_lossy(&contents)
+    ));
+}
+
+#[test]
+fn read_file() {
+    let mut fs = Vfs::memory();
+    fs.insert("dir/in.txt", "abcdef");
+    let vm = run_fs(
+        "
+    name str \"./dir/in.txt\", 0
+    buf str 0, 0, 0, 0
+_start:
+    mov rax, 2
+    mov rdi, name
+    mov rsi, 0
+    syscall
+    mov rdi, rax
+    mov rax, 0
+    mov rsi, buf
+    mov rdx, 4
+    syscall",
+        fs,
+    );
+    let buf = vm.mem.windows(4).any(|w| w == b"abcd");
+    expect!["rax=4 rdi=3 true"].assert_eq(&format!(
+        "rax={} rdi={} {buf}",
+        vm.reg(Register::RAX),
+        vm.reg(Register::RDI)
+    ));
+}
+
+#[test]
+fn open_errors() {
+    for (name, flags) in [("missing", 0), ("../escape", 65), ("/etc/passwd", 0)] {
+        let src = format!(
+            "
+    name str \"{name}\", 0
+_start:
+    mov rax, 2
+    mov rdi, name
+    mov rsi, {flags}
+    syscall"
+        );
+        let vm = run_fs(&src, Vfs::memory());
+        let errno = (vm.reg(Register::RAX) as i16).to_string();
+        let expect = match name {
+            "missing" => expect!["-2"],
+            _ => expect!["-13"],
+        };
+        expect.assert_eq(&errno);
+    }
+}
+
+#[test]
+fn bad_fd() {
+    let vm = run_fs(
+        "
+_start:
+    mov rax, 3
+    mov rdi, 4
+    syscall",
+        Vfs::memory(),
+    );
+    expect!["-9"].assert_eq(&(vm.reg(Register::RAX) as i16).to_string());
+}
+
+#[test]
+fn host_fs() {
+    let root = std::env::temp_dir().join(format!("basm-vm-test-{}", std::process::id()));
+    std::fs::create_dir_all(&root).unwrap();
+    let vm = run_fs(
+        "
+    name str \"host.txt\", 0
+    text str \"on disk\"
+_start:
+    mov rax, 2
+    mov rdi, name
+    mov rsi, 577
+    syscall
+    mov rdi, rax
+    mov rax, 1
+    mov rsi, text
+    mov rdx, 7
+    syscall",
+        Vfs::host(&root),
+    );
+    let on_disk = std::fs::read_to_string(root.join("host.txt"));
+    std::fs::remove_dir_all(&root).unwrap();
+    expect![[r#"rax=7 Ok("on disk")"#]]
+        .assert_eq(&format!("rax={} {on_disk:?}", vm.reg(Register::RAX)));
+}
+
+#[cfg(unix)]
+#[test]
+fn host_fs_symlinks() {
+    use crate::fs::{O_CREAT, O_RDONLY, O_WRONLY};
+    use std::os::unix::fs::symlink;
+
+    let dir = std::env::temp_dir().join(format!("basm-vm-links-{}", std::process::id()));
+    let (root, outside) = (dir.join("root"), dir.join("outside"));
+    std::fs::create_dir_all(&root).unwrap();
+    std::fs::create_dir_all(&outside).unwrap();
+    std::fs::write(outside.join("secret.txt"), "secret").unwrap();
+    std::fs::write(root.join("inside.txt"), "inside").unwrap();
+    symlink(&outside, root.join("out")).unwrap();
+    symlink(outside.join("new.txt"), root.join("dangling")).unwrap();
+    symlink(root.join("inside.txt"), root.join("link.txt")).unwrap();
+
+    let mut fs = Vfs::host(&root);
+    let opened = [
+        fs.open("out/secret.txt", O_RDONLY),
+        fs.open("dangling", O_WRONLY | O_CREAT),
+        fs.open("out/new.txt", O_WRONLY | O_CREAT),
+        fs.open("link.txt", O_RDONLY),
+    ];
+    let leaked = fs.contents("out/secret.txt");
+    let created = outside.join("new.txt").exists();
+    std::fs::remove_dir_all(&dir).unwrap();
+    // links may only lead to files within the root
+    expect!["[Err(13), Err(13), Err(13), Ok(3)] None false"]
+        .assert_eq(&format!("{opened:?} {leaked:?} {created}"));
+}
+
+const HELLO_NAME: &str = "
+    prompt str \"name? \"
+    hello str \"hello \"
+    name str 0, 0, 0, 0, 0, 0, 0, 0
+_start:
+    mov rax, 1
+    mov rdi, 1
+    mov rsi, prompt
+    mov rdx, 6
+    syscall
+    mov rax, 0
+    mov rdi, 0
+    mov rsi, name
+    mov rdx, 8
+    syscall
+    mov rbx, rax
+    mov rax, 1
+    mov rdi, 1
+    mov rsi, hello
+    mov rdx, 6
+    syscall
+    mov rax, 1
+    mov rsi, name
+    mov rdx, rbx
+    syscall";
+
+#[test]
+fn read_stdin() {
+    check_input_vm(
+        parse(HELLO_NAME),
+        Scripted::lines(["alice\n", "bob\n"]),
+        expect![[r#"
+            stdout: "name? hello alice\n"
+            stderr: ""
+            rax: 6"#]],
+    );
+}
+
+#[test]
+fn read_stdin_truncated() {
+    check_input_vm(
+        parse(HELLO_NAME),
+        Scripted::lines(["bartholomew\n"]),
+        expect![[r#"
+            stdout: "name? hello bartholo"
+            stderr: ""
+            rax: 8"#]],
+    );
+}
+
+#[test]
+fn read_stdin_eof() {
+    check_input_vm(
+        parse(HELLO_NAME),
+        Scripted::default(),
+        expect![[r#"
+            stdout: "name? hello "
+            stderr: ""
+            rax: 0"#]],
+    );
+}
+
+const SPIN: &str = "
+_start:
+    inc rax
+spin:
+    inc rbx
+    jmp spin";
+
+#[test]
+fn instruction_budget() {
+    let mut vm = parse(SPIN);
+    vm.options.max_instructions = Some(100);
+    check_vm(
+        vm,
+        expect!["BudgetExhausted(Instructions) at 0x0016 RAX=1 RBX=50 []"],
+    );
+}
+
+#[test]
+fn budget_not_reached() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 1
+    mov rbx, 2",
+    );
+    vm.options.max_instructions = Some(2);
+    check_vm(vm, expect!["RAX=1 RBX=2 []"]);
+}
+
+#[test]
+fn time_limit() {
+    let mut vm = parse(SPIN);
+    vm.options.time_limit = Some(std::time::Duration::from_millis(10));
+    let outcome = vm.run();
+    assert_eq!(outcome.reason, Termination::BudgetExhausted(Budget::Time));
+    assert_eq!(outcome.exit_code, None);
+    assert_eq!(vm.rip, outcome.registers.rip);
+}
+
+fn check_steps(src: &str, expect: Expect) {
+    let mut vm = parse(src).with_io(VmIo::new(
+        std::io::empty(),
+        std::io::sink(),
+        std::io::sink(),
+    ));
+    vm.reset();
+    let mut events = Vec::new();
+    loop {
+        let event = vm.step();
+        let done = !matches!(
+            event,
+            StepEvent::Executed { .. } | StepEvent::Syscall { .. }
+        );
+        events.push(match event {
+            StepEvent::Faulted(err) => format!("Faulted({:?} at {:#06x})", err.kind, err.address),
+            event => format!("{event:?}"),
+        });
+        if done {
+            break;
+        }
+    }
+    expect.assert_eq(&events.join("\n"));
+}
+
+#[test]
+fn step_exit() {
+    check_steps(
+        "
+    msg str \"x\"
+_start:
+    mov rax, 1
+    mov rdi, 1
+    mov rsi, msg
+    mov rdx, 1
+    syscall
+    mov rax, 60
+    mov rdi, 3
+    syscall
+    mov rax, 1",
+        expect![[r#"
+            Executed { address: 9 }
+            Executed { address: 17 }
+            Executed { address: 25 }
+            Executed { address: 33 }
+            Syscall { address: 41, number: 1 }
+            Executed { address: 49 }
+            Executed { address: 57 }
+            Exited(3)"#]],
+    );
+}
+
+#[test]
+fn step_end() {
+    check_steps(
+        "
+_start:
+    jmp end
+    mov rax, 1
+end:
+    inc rax",
+        expect![[r#"
+            Executed { address: 6 }
+            Executed { address: 22 }
+            End"#]],
+    );
+}
+
+#[test]
+fn step_fault() {
+    check_steps(
+        "
+_start:
+    pop rax",
+        expect!["Faulted(StackUnderflow at 0x0006)"],
+    );
+}
+
+#[test]
+fn run_twice() {
+    let mut vm = parse(
+        "
+    count str 0
+_start:
+    mov rax, [count]
+    inc rax
+    mov [count], rax
+    mov rdi, rax
+    mov rax, 60
+    syscall",
+    );
+    assert_eq!(vm.run().exit_code, Some(1));
+    assert_eq!(vm.run().exit_code, Some(1));
+}
+
+#[test]
+fn step_after_halt() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 60
+    mov rdi, 1
+    syscall",
+    );
+    assert_eq!(vm.run().exit_code, Some(1));
+    assert_eq!(vm.step(), StepEvent::Exited(1));
+    assert_eq!(vm.executed, 3);
+}
+
+#[test]
+fn run_outcome() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 60
+    mov rdi, 7
+    syscall",
+    );
+    let outcome = vm.run();
+    expect!["Exited Some(7) 3 0x001e"].assert_eq(&format!(
+        "{:?} {:?} {} {:#06x}",
+        outcome.reason, outcome.exit_code, outcome.executed, outcome.registers.rip
+    ));
+    assert_eq!(
+        std::process::ExitCode::from(outcome),
+        std::process::ExitCode::from(7)
+    );
+}
+
+#[test]
+fn faulted_outcome() {
+    let mut vm = parse(
+        "
+_start:
+    mov rax, 1
+    div rbx",
+    );
+    let outcome = vm.run();
+    assert_eq!(outcome.exit_code, None);
+    assert_eq!(outcome.executed, 2);
+    assert_eq!(
+        outcome.error().map(|err| err.kind),
+        Some(FaultKind::DivisionByZero)
+    );
+    assert_eq!(
+        std::process::ExitCode::from(outcome),
+        std::process::ExitCode::FAILURE
+    );
+}
+
+fn check_trace(src: &str, json: bool, expect: Expect) {
+    let out = Captured::default();
+    let vm = match json {
+        true => parse(src).with_tracer(crate::trace::JsonTracer(out.clone())),
+        false => parse(src).with_tracer(crate::trace::TextTracer(out.clone())),
+    };
+    let mut vm = vm.with_io(VmIo::new(
+        std::io::empty(),
+        std::io::sink(),
+        std::io::sink(),
+    ));
+    vm.run();
+    expect.assert_eq(&out.to_string_lossy());
+}
+
+const TRACED: &str = "
+    num str 0, 0
+_start:
+    mov rax, 2
+    mov [num], al
+    push rax
+    sub rax, 3
+    call done
+done:
+    mov rax, 60
+    syscall";
+
+#[test]
+fn trace_text() {
+    check_trace(
+        TRACED,
+        false,
+        expect![[r#"
+        0x000a  mov rax, 2              rax: 0x0000 -> 0x0002
+        0x0012  mov [0x0008], al        [0x0008]: 00 -> 02
+        0x001a  push rax                rsp: 0xffff -> 0xfffd, [0xfffd]: 00 00 -> 02 00
+        0x0022  sub rax, 3              rax: 0x0002 -> 0xffff, flags: [] -> [Sf Cf Af Pf]
+        0x002a  call 0x0032             rsp: 0xfffd -> 0xfffb, [0xfffb]: 00 00 -> 32 00
+        0x0032  mov rax, 60             rax: 0xffff -> 0x003c
+        0x003a  syscall
+    "#]],
+    );
+}
+
+#[test]
+fn trace_json() {
+    check_trace(
+        TRACED,
+        true,
+        expect![[r#"
+        {"address":10,"flags":null,"registers":{"rax":[0,2]},"sequence":"mov rax, 2","step":1,"writes":[]}
+        {"address":18,"flags":null,"registers":{},"sequence":"mov [0x0008], al","step":2,"writes":[{"address":8,"new":[2],"old":[0]}]}
+        {"address":26,"flags":null,"registers":{"rsp":[65535,65533]},"sequence":"push rax","step":3,"writes":[{"address":65533,"new":[2,0],"old":[0,0]}]}
+        {"address":34,"flags":[[],["Sf","Cf","Af","Pf"]],"registers":{"rax":[2,65535]},"sequence":"sub rax, 3","step":4,"writes":[]}
+        {"address":42,"flags":null,"registers":{"rsp":[65533,65531]},"sequence":"call 0x0032","step":5,"writes":[{"address":65531,"new":[50,0],"old":[0,0]}]}
+        {"address":50,"flags":null,"registers":{"rax":[65535,60]},"sequence":"mov rax, 60","step":6,"writes":[]}
+        {"address":58,"flags":null,"registers":{},"sequence":"syscall","step":7,"writes":[]}
+    "#]],
+    );
+}
+
+#[test]
+fn disassemble() {
+    let vm = parse(
+        "
+    msg str \"hi\"
+_start:
+    mov al, [msg]
+    mov [rbx], ch
+    shl r08b, cl
+    cmp rsi, 10
+    push msg
+    jmp _start
+    ret",
+    );
+    let seqs: Vec<_> = crate::decode::decode(&vm.mem)
+        .map(|s| s.to_string())
+        .collect();
+    expect![[r#"
+        mov al, [0x0008]
+        mov [rbx], ch
+        shl r08b, cl
+        cmp rsi, 10
+        push 0x0008
+        jmp 0x000a
+        ret"#]]
+    .assert_eq(&seqs.join("\n"));
+}
+
+#[test]
+fn symbols() {
+    let vm = parse(
+        "
+    first str \"ab\"
+    second str 1, 2, 3
+
+_start:
+    mov rax, 1
+    call print
+    ret
+print:
+    mov rax, [first]
+    ret",
+    );
+    let symbols = &vm.symbols;
+    let lines: Vec<_> = [15, 23, 31, 39, 47, 48]
+        .into_iter()
+        .map(|address| format!("{address:#06x} {}", symbols.describe(address)))
+        .collect();
+    expect![[r#"
+        [("_start", 15), ("print", 39)]
+        [Variable { name: "first", address: 8, len: 2 }, Variable { name: "second", address: 12, len: 3 }]
+        0x000f _start (line 6)
+        0x0017 _start+8 (line 7)
+        0x001f _start+16 (line 8)
+        0x0027 print (line 10)
+        0x002f print+8 (line 11)
+        0x0030 print+9
+        line 9: Some(39)"#]].assert_eq(&format!(
+        "{:?}\n{:?}\n{}\nline 9: {:?}",
+        symbols.labels,
+        symbols.variables,
+        lines.join("\n"),
+        symbols.line_address(8),
+    ));
+}
+
+const UNDONE: &str = "
+    buf str \"ab\"
+
+_start:
+    mov rax, 7
+    push rax
+    mov bl, 122
+    mov [buf], bl
+    add rax, 250
+    pop rbx
+    mov rcx, 0
+    div rcx";
+
+#[test]
+fn step_back() {
+    let mut vm = parse(UNDONE);
+    vm.options.history = 16;
+    vm.reset();
+    let mut states = vec![(vm.registers(), vm.mem)];
+    while matches!(vm.step(), StepEvent::Executed { .. }) {
+        states.push((vm.registers(), vm.mem));
+    }
+    assert!(matches!(vm.step(), StepEvent::Faulted(_)));
+    assert_eq!(vm.history_len(), 8);
+    // the fault leaves everything but the count as it was
+    assert!(vm.step_back());
+    assert_eq!(vm.executed, 7);
+    while let Some((registers, mem)) = states.pop() {
+        assert_eq!(vm.registers(), registers);
+        assert!(vm.mem == mem);
+        assert_eq!(vm.step_back(), !states.is_empty());
+    }
+    assert_eq!(vm.executed, 0);
+}
+
+#[test]
+fn rewind() {
+    let mut vm = parse(UNDONE);
+    vm.options.history = 3;
+    let outcome = vm.run();
+    assert_eq!(outcome.executed, 8);
+    assert_eq!(vm.history_len(), 3);
+    assert!(!vm.rewind_to(9));
+    assert!(!vm.rewind_to(4));
+    assert!(vm.rewind_to(5));
+    expect![[r#"0x0032 rax: 0x0101 rbx: 0x007a buf: "zb""#]].assert_eq(&format!(
+        "{:#06x} rax: {:#06x} rbx: {:#06x} buf: {:?}",
+        vm.rip,
+        vm.reg(Register::RAX),
+        vm.reg(Register::RBX),
+        String::from_utf8_lossy(
+            vm.slice(vm.symbols.variable("buf").unwrap().address, 2)
+                .unwrap()
+        ),
+    ));
+    // running on from the rewound state repeats the same fault
+    assert!(matches!(vm.step(), StepEvent::Executed { .. }));
+    assert!(matches!(vm.step(), StepEvent::Executed { .. }));
+    assert!(matches!(vm.step(), StepEvent::Faulted(_)));
+    assert_eq!(vm.history_len(), 3);
+}
+
+const NESTED: &str = "
+_start:
+    mov rcx, 0
+loop:
+    call outer
+    inc rcx
+    cmp rcx, 3
+    jne loop
+    mov rax, 60
+    mov rdi, 0
+    syscall
+
+outer:
+    call inner
+    add rbx, 1
+    ret
+
+inner:
+    add rbx, 2
+    ret";
+
+#[test]
+fn profile() {
+    let mut vm = parse(NESTED).with_profiler();
+    vm.run();
+    let Some(profiler) = &vm.profiler else {
+        panic!("the profiler was dropped");
+    };
+    assert_eq!(profiler.total(), vm.executed);
+    assert_eq!(profiler.hits(vm.symbols.label("inner").unwrap()), 3);
+    expect![[r#"
+        31 instructions executed
+
+             count      %  label
+                15  48.4%  loop
+                 9  29.0%  outer
+                 6  19.4%  inner
+                 1   3.2%  _start
+
+             count      %  instruction
+                 3   9.7%  0x000e loop (line 5)
+                 3   9.7%  0x0016 loop+8 (line 6)
+                 3   9.7%  0x001e loop+16 (line 7)
+                 3   9.7%  0x0026 loop+24 (line 8)
+                 3   9.7%  0x0046 outer (line 14)
+                 3   9.7%  0x004e outer+8 (line 15)
+                 3   9.7%  0x0056 outer+16 (line 16)
+                 3   9.7%  0x005e inner (line 19)
+                 3   9.7%  0x0066 inner+8 (line 20)
+                 1   3.2%  0x0006 _start (line 3)
+    "#]]
+    .assert_eq(&profiler.report(&vm.symbols));
+    expect![[r#"
+        _start 16
+        _start;outer 9
+        _start;outer;inner 6
+    "#]]
+    .assert_eq(&profiler.collapsed(&vm.symbols));
+}
+
+#[test]
+fn coverage() {
+    let src = "
+    buf str 0
+_start:
+    mov rsi, buf
+    mov rdx, 1
+    syscall
+    cmp rax, 0
+    je skip
+    mov rax, 1
+skip:
+    cmp rax, 5
+    ja never
+    mov rax, 60
+    syscall
+never:
+    mov rax, 60
+    mov rdi, 1
+    syscall";
+    let mut vm = parse(src).with_coverage();
+    // one run reads a byte & the other reaches the end of its input
+    for input in [Scripted::lines(["x"]), Scripted::default()] {
+        vm.io = VmIo::new(input, std::io::sink(), std::io::sink());
+        vm.run();
+    }
+    let Some(coverage) = &vm.coverage else {
+        panic!("the coverage was dropped");
+    };
+    expect![[r#"
+        TN:
+        SF:branches.basm
+        BRDA:8,4,0,1
+        BRDA:8,4,1,1
+        BRDA:12,7,0,0
+        BRDA:12,7,1,2
+        BRF:4
+        BRH:3
+        DA:4,2
+        DA:5,2
+        DA:6,2
+        DA:7,2
+        DA:8,2
+        DA:9,1
+        DA:11,2
+        DA:12,2
+        DA:13,2
+        DA:14,2
+        DA:16,0
+        DA:17,0
+        DA:18,0
+        LF:13
+        LH:10
+        end_of_record
+    "#]]
+    .assert_eq(&coverage.lcov(&vm, "branches.basm"));
+}
+
+#[test]
+fn snapshot() {
+    let src = "
+    name str \"in.txt\", 0
+    buf str 0, 0
+_start:
+    mov rax, 2
+    mov rdi, name
+    mov rsi, 0
+    syscall
+    mov rbx, rax
+    mov rax, 0
+    mov rdi, rbx
+    mov rsi, buf
+    mov rdx, 2
+    syscall
+    cmp rax, 2
+checkpoint:
+    mov rax, 0
+    mov rdi, rbx
+    mov rsi, buf
+    mov rdx, 2
+    syscall
+    mov rax, 1
+    mov rdi, 1
+    syscall
+    mov rax, 60
+    mov rdi, rbx
+    syscall";
+    let mut fs = Vfs::memory();
+    fs.insert("in.txt", "abcdef");
+    let mut vm = parse(src).with_fs(fs);
+    vm.options.trap_overflow = true;
+    vm.reset();
+    let checkpoint = vm.symbols.label("checkpoint").unwrap();
+    while vm.rip != checkpoint {
+        vm.step();
+    }
+    let saved = vm.snapshot().to_string();
+    let Ok(snapshot) = serde_json::from_str(&saved) else {
+        panic!("the snapshot is not json");
+    };
+    let restored = match BasmVM::restore(&snapshot, None) {
+        Ok(vm) => vm,
+        Err(err) => panic!("failed to restore: {err}"),
+    };
+    assert_eq!(restored.registers(), vm.registers());
+    assert_eq!(restored.symbols, vm.symbols);
+    assert!(restored.mem == vm.mem);
+    assert!(restored.options.trap_overflow);
+    // both carry on reading the open file from where it was
+    for mut vm in [vm, restored] {
+        let stdout = Captured::default();
+        vm.io = VmIo::new(std::io::empty(), stdout.clone(), std::io::sink());
+        let outcome = vm.resume();
+        expect![[r#"Some(3) 22 "cd""#]].assert_eq(&format!(
+            "{:?} {} {:?}",
+            outcome.exit_code,
+            outcome.executed,
+            stdout.to_string_lossy()
+        ));
+    }
+}
+
+#[test]
+fn snapshot_after_exit() {
+    let mut vm = parse(
+        "
+    count str 0
+_start:
+    mov rax, [count]
+    inc rax
+    mov [count], rax
+    mov rdi, rax
+    mov rax, 60
+    syscall
+    mov rbx, 99
+    mov rdi, 9
+    syscall",
+    );
+    assert_eq!(vm.run().exit_code, Some(1));
+    let restore = || match BasmVM::restore(&vm.snapshot(), None) {
+        Ok(vm) => vm,
+        Err(err) => panic!("failed to restore: {err}"),
+    };
+    // the exit is kept, rather than running on past it
+    let mut restored = restore();
+    let outcome = restored.resume();
+    assert_eq!(outcome.exit_code, Some(1));
+    assert_eq!(restored.reg(Register::RBX), 0);
+    // a rerun starts from the program as loaded, not as it was left
+    assert_eq!(restore().run().exit_code, Some(1));
+}
+
+#[test]
+fn snapshot_errors() {
+    let errors: Vec<_> = [
+        serde_json::json!({ "rip": 0 }),
+        serde_json::json!({ "format": crate::snapshot::FORMAT, "version": 99 }),
+        serde_json::json!({ "format": crate::snapshot::FORMAT, "version": 1 }),
+    ]
+    .iter()
+    .map(|snapshot| match BasmVM::restore(snapshot, None) {
+        Ok(_) => "restored".to_string(),
+        Err(err) => err.to_string(),
+    })
+    .collect();
+    expect![[r#"
+        not a basm-vm snapshot
+        unsupported snapshot version 99, expected 2
+        missing or malformed field `memory`"#]]
+    .assert_eq(&errors.join("\n"));
+}
+
+#[test]
+fn snapshot_untrusted() {
+    let mut vm = parse("_start:\n    mov rax, 60\n    syscall");
+    vm.reset();
+    let snapshot = vm.snapshot();
+    let tamper = |pointer: &str, value: serde_json::Value| {
+        let mut snapshot = snapshot.clone();
+        *snapshot.pointer_mut(pointer).unwrap() = value;
+        match BasmVM::restore(&snapshot, None) {
+            Ok(_) => "restored".to_string(),
+            Err(err) => err.to_string(),
+        }
+    };
+    let fd = |path: &str| {
+        serde_json::json!([{
+            "fd": 3, "path": path, "pos": 0, "read": true, "write": false, "append": false,
+        }])
+    };
+    let errors = [
+        tamper("/memory/0/address", serde_json::json!(u64::MAX)),
+        tamper("/fs/fds", fd("/etc/passwd")),
+        tamper("/fs/fds", fd("../outside.txt")),
+        tamper("/fs/root", serde_json::json!({ "host": "/" })),
+        tamper("/calls", serde_json::Value::Null),
+        // version 1 came before calls were kept
+        tamper("/version", serde_json::json!(1)),
+    ];
+    expect![[r#"
+        missing or malformed field `memory.address`
+        missing or malformed field `fs.fds`
+        missing or malformed field `fs.fds`
+        the snapshot's files are on the host, but no root was given
+        missing or malformed field `calls`
+        restored"#]]
+    .assert_eq(&errors.join("\n"));
+}
+
+const ECHO: &str = "
+    name str \"in.txt\", 0
+    buf str 0, 0, 0, 0, 0, 0
+_start:
+    mov rax, 0
+    mov rdi, 0
+    mov rsi, buf
+    mov rdx, 3
+    syscall
+    mov rdx, rax
+    mov rax, 1
+    mov rdi, 1
+    syscall
+    mov rax, 2
+    mov rdi, name
+    mov rsi, 0
+    syscall
+    mov rdi, rax
+    mov rax, 0
+    mov rsi, buf
+    mov rdx, 6
+    syscall
+    mov rdx, rax
+    mov rax, 1
+    mov rdi, 1
+    syscall
+    mov rdi, rdx
+    mov rax, 60
+    syscall";
+
+/// runs a program reading `stdin`, returning how it ended & its output
+fn run_echo(vm: &mut BasmVM, stdin: &str) -> String {
+    let stdout = Captured::default();
+    vm.io = VmIo::new(Scripted::lines([stdin]), stdout.clone(), std::io::sink());
+    let outcome = vm.run();
+    let fault = outcome
+        .error()
+        .map(|err| format!(" {}", err.kind))
+        .unwrap_or_default();
+    format!(
+        "{:?} {:?}{fault}",
+        outcome.exit_code,
+        stdout.to_string_lossy()
+    )
+}
+
+#[test]
+fn record_replay() {
+    let mut fs = Vfs::memory();
+    fs.insert("in.txt", "file!");
+    let mut vm = parse(ECHO).with_fs(fs);
+    vm.replay = Some(Replay::record());
+    let recorded = run_echo(&mut vm, "abc");
+    let Some(replay) = vm.replay.take() else {
+        panic!("the recording was dropped");
+    };
+    let Ok(log) = ReplayLog::from_json(&replay.log().to_json()) else {
+        panic!("the recording does not round trip");
+    };
+    assert_eq!(&log, replay.log());
+    expect![[r#"Some(5) "abcfile!""#]].assert_eq(&recorded);
+
+    // neither the input nor the file are there, yet the run is the same
+    let mut vm = parse(ECHO);
+    vm.replay = Some(Replay::play(log.clone()));
+    assert_eq!(run_echo(&mut vm, ""), recorded);
+
+    // a different program gives itself away at its first differing syscall
+    let mut vm = parse(HELLO_NAME);
+    vm.replay = Some(Replay::play(log));
+    expect![[r#"None "" replay diverged, syscall 0x1 where 0x0 was recorded"#]]
+        .assert_eq(&run_echo(&mut vm, "abc"));
+}
+
+#[test]
+fn replay_step_back() {
+    let mut fs = Vfs::memory();
+    fs.insert("in.txt", "file!");
+    let mut vm = parse(ECHO).with_fs(fs);
+    vm.options.history = 16;
+    vm.replay = Some(Replay::record());
+    vm.io = VmIo::new(Scripted::lines(["abc"]), std::io::sink(), std::io::sink());
+    vm.reset();
+    // stepping back over the read forgets it was recorded
+    for _ in 0..5 {
+        vm.step();
+    }
+    let position = |vm: &BasmVM| vm.replay.as_ref().map(|replay| replay.log().syscalls.len());
+    assert_eq!(position(&vm), Some(1));
+    assert!(vm.step_back());
+    assert_eq!(position(&vm), Some(0));
+    let stdout = Captured::default();
+    vm.io = VmIo::new(Scripted::lines(["abc"]), stdout.clone(), std::io::sink());
+    assert_eq!(vm.resume().exit_code, Some(5));
+    let Some(replay) = vm.replay.take() else {
+        panic!("the recording was dropped");
+    };
+    assert_eq!(replay.log().syscalls.len(), 6);
+
+    // playing back the read again takes the same result from the log
+    let mut vm = parse(ECHO);
+    vm.options.history = 16;
+    vm.replay = Some(Replay::play(replay.log().clone()));
+    let stdout = Captured::default();
+    vm.io = VmIo::new(std::io::empty(), stdout.clone(), std::io::sink());
+    vm.reset();
+    for _ in 0..5 {
+        vm.step();
+    }
+    assert!(vm.step_back());
+    let outcome = vm.resume();
+    expect![[r#"Some(5) "abcfile!""#]].assert_eq(&format!(
+        "{:?} {:?}",
+        outcome.exit_code,
+        stdout.to_string_lossy()
+    ));
+}
+
+/// the fault & backtrace a program ends with
+fn check_backtrace(src: &str, expect: Expect) {
+    let mut vm = parse(src);
+    let outcome = vm.run();
+    let fault = match outcome.error() {
+        Some(err) => err.kind.to_string(),
+        None => format!("{:?}", outcome.reason),
+    };
+    let backtrace = vm.symbols.backtrace(&vm.backtrace());
+    expect.assert_eq(&format!("{fault}\n{backtrace}"));
+}
+
+#[test]
+fn backtrace() {
+    check_backtrace(
+        "
+_start:
+    call outer
+    mov rax, 60
+    syscall
+
+outer:
+    mov rcx, 0
+    call inner
+    ret
+
+inner:
+    div rcx
+    ret",
+        expect![[r#"
+            division by zero
+            #0 0x0036 inner (line 13)
+            #1 0x0026 outer+8 (line 9)
+            #2 0x0006 _start (line 3)
+        "#]],
+    );
+}
+
+#[test]
+fn unbalanced_push() {
+    check_backtrace(
+        "
+_start:
+    call leaky
+    mov rax, 60
+    syscall
+
+leaky:
+    push rax
+    ret",
+        expect![[r#"
+            return to 0x0000 rather than 0x000e, the stack holds 2 bytes more than after the call
+            #0 0x0026 leaky+8 (line 9)
+            #1 0x0006 _start (line 3)
+        "#]],
+    );
+}
+
+#[test]
+fn overwritten_return() {
+    check_backtrace(
+        "
+_start:
+    call clobber
+    mov rax, 60
+    syscall
+
+clobber:
+    pop rbx
+    push 0
+    ret",
+        expect![[r#"
+            return to 0x0000 rather than 0x000e, the return address was overwritten
+            #0 0x002e clobber+16 (line 10)
+            #1 0x0006 _start (line 3)
+        "#]],
+    );
+}
+
+#[test]
+fn corrupt_return_keeps_stack() {
+    let mut vm = parse(
+        "
+_start:
+    call leaky
+    mov rax, 60
+    syscall
+
+leaky:
+    push 5
+    ret",
+    );
+    let outcome = vm.run();
+    let Some(err) = outcome.error() else {
+        panic!("expected a fault");
+    };
+    // the value ret found is still on top of the stack, under the call
+    assert_eq!(err.registers.reg[Register::RSP as usize], 0xfffb);
+    assert_eq!(vm.load(0xfffb, Width::Word), Ok(5));
+    assert_eq!(vm.calls().len(), 1);
+}
+
+#[test]
+fn step_back_calls() {
+    let mut vm = parse(NESTED);
+    vm.options.history = 64;
+    vm.reset();
+    let mut depths = Vec::new();
+    for _ in 0..12 {
+        depths.push(vm.calls().to_vec());
+        vm.step();
+    }
+    while let Some(calls) = depths.pop() {
+        assert!(vm.step_back());
+        assert_eq!(vm.calls(), calls);
+    }
+}
+
+#[test]
+fn program_too_large() {
+    let src = format!("_start:\n{}", "    inc rax\n".repeat(9000));
+    let res = BasmVM::parse(&src).map(|_| ());
+    let Err(crate::VmError::EncodeError(errs)) = res else {
+        panic!("a program larger than memory was encoded");
+    };
+    expect!["[OutOfMemory { needed: 72006 }]"].assert_eq(&format!("{errs:?}"));
+}
+
+#[test]
+fn undefined_labels() {
+    let res = BasmVM::parse(
+        "
+_start:
+    jmp nowhere
+    call missing
+    mov rax, 60
+    syscall",
+    )
+    .map(|_| ());
+    let Err(crate::VmError::EncodeError(errs)) = res else {
+        panic!("a program with undefined labels was encoded");
+    };
+    expect![[r#"[MissingSymbol("nowhere"), MissingSymbol("missing")]"#]]
+        .assert_eq(&format!("{errs:?}"));
+}
@@ -0,0 +1,168 @@
+use std::io::Write;
+
+use serde_json::json;
+
+use crate::{Flag, Register, Registers, Sequence, REGISTER_COUNT};
+
+/// receives a record of every instruction the vm executes
+pub trait Tracer: Send {
+    fn trace(&mut self, record: &TraceRecord);
+}
+
+impl std::fmt::Debug for dyn Tracer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Tracer")
+    }
+}
+
+impl<F> Tracer for F
+where
+    F: FnMut(&TraceRecord) + Send,
+{
+    fn trace(&mut self, record: &TraceRecord) {
+        self(record)
+    }
+}
+
+/// what a single instruction did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    /// the number of instructions executed so far, counting this one
+    pub step: u64,
+    pub address: u16,
+    pub sequence: String,
+    pub registers: Vec<RegisterChange>,
+    /// the flags before & after, if they changed
+    pub flags: Option<(u16, u16)>,
+    pub writes: Vec<MemWrite>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegisterChange {
+    pub reg: Register,
+    pub old: u16,
+    pub new: u16,
+}
+
+/// bytes stored into memory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemWrite {
+    pub address: u16,
+    pub old: Box<[u8]>,
+    pub new: Box<[u8]>,
+}
+
+impl TraceRecord {
+    pub(crate) fn new(
+        step: u64,
+        address: u16,
+        sequence: &Sequence,
+        before: &Registers,
+        after: &Registers,
+        writes: Vec<MemWrite>,
+    ) -> Self {
+        let registers = (0..REGISTER_COUNT)
+            .filter(|&i| before.reg[i] != after.reg[i])
+            .filter_map(|i| {
+                Some(RegisterChange {
+                    reg: Register::try_from(i as u16).ok()?,
+                    old: before.reg[i],
+                    new: after.reg[i],
+                })
+            })
+            .collect();
+        Self {
+            step,
+            address,
+            sequence: sequence.to_string(),
+            registers,
+            flags: (before.flag != after.flag).then_some((before.flag, after.flag)),
+            writes,
+        }
+    }
+
+    /// the record as a single line of json
+    pub fn to_json(&self) -> String {
+        let registers: serde_json::Map<_, _> = self
+            .registers
+            .iter()
+            .map(|c| (c.reg.to_string(), json!([c.old, c.new])))
+            .collect();
+        let flags = self
+            .flags
+            .map(|(old, new)| json!([flag_names(old), flag_names(new)]));
+        let writes: Vec<_> = self
+            .writes
+            .iter()
+            .map(|w| json!({ "address": w.address, "old": w.old, "new": w.new }))
+            .collect();
+        json!({
+            "step": self.step,
+            "address": self.address,
+            "sequence": self.sequence,
+            "registers": registers,
+            "flags": flags,
+            "writes": writes,
+        })
+        .to_string()
+    }
+}
+
+impl std::fmt::Display for TraceRecord {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut changes = Vec::new();
+        for c in &self.registers {
+            changes.push(format!("{}: {:#06x} -> {:#06x}", c.reg, c.old, c.new));
+        }
+        if let Some((old, new)) = self.flags {
+            changes.push(format!(
+                "flags: [{}] -> [{}]",
+                flag_names(old).join(" "),
+                flag_names(new).join(" ")
+            ));
+        }
+        for w in &self.writes {
+            changes.push(format!(
+                "[{:#06x}]: {} -> {}",
+                w.address,
+                hex(&w.old),
+                hex(&w.new)
+            ));
+        }
+        write!(f, "{:#06x}  {:<24}", self.address, self.sequence)?;
+        write!(f, "{}", changes.join(", ").trim_end())
+    }
+}
+
+fn flag_names(flag: u16) -> Vec<String> {
+    Flag::ALL
+        .into_iter()
+        .filter(|&fl| flag & fl as u16 != 0)
+        .map(|fl| format!("{fl:?}"))
+        .collect()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let bytes: Vec<_> = bytes.iter().map(|b| format!("{b:02x}")).collect();
+    bytes.join(" ")
+}
+
+/// writes each record as a line of text
+#[derive(Debug)]
+pub struct TextTracer<W>(pub W);
+
+impl<W: Write + Send> Tracer for TextTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        let _ = writeln!(self.0, "{}", record.to_string().trim_end());
+    }
+}
+
+/// writes each record as a line of json
+#[derive(Debug)]
+pub struct JsonTracer<W>(pub W);
+
+impl<W: Write + Send> Tracer for JsonTracer<W> {
+    fn trace(&mut self, record: &TraceRecord) {
+        let _ = writeln!(self.0, "{}", record.to_json());
+    }
+}